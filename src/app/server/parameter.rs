@@ -1,10 +1,11 @@
-use std::{env::args, net::Ipv4Addr};
+use std::{env::args, net::Ipv4Addr, path::PathBuf};
 
 pub enum Param {
     Short {
         ip: Ipv4Addr,
         port: i64,
         id: i64,
+        data_file: Option<PathBuf>,
     },
     Long {
         ip_local: Ipv4Addr,
@@ -12,22 +13,70 @@ pub enum Param {
         id_local: i64,
         ip_destination: Ipv4Addr,
         port_destination: i64,
+        data_file: Option<PathBuf>,
     },
 }
 
-pub fn get_args() -> Option<Param> {
-    let args: Vec<String> = args().collect();
+/// Settings given as `--name value` anywhere on the command line.
+pub struct Options {
+    /// Number of successors keeping a copy of every entry, 0 disables replication.
+    pub replicas: i64,
+    /// Number of ring positions held by the server, spread evenly from its id.
+    pub vnodes: i64,
+    /// Whether the fingers are picked by round trip time, `on` or `off`.
+    pub proximity: bool,
+    /// Whether requests for keys the node does not own are redirected, `on` or `off`.
+    pub redirect: bool,
+}
+
+fn on_off(value: &str) -> Option<bool> {
+    match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Removes the `--name value` options from `args`.
+fn take_options(args: Vec<String>) -> Option<(Vec<String>, Options)> {
+    let mut options: Options = Options {
+        replicas: 0,
+        vnodes: 1,
+        proximity: false,
+        redirect: false,
+    };
+    let mut positional: Vec<String> = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--replicas" => options.replicas = args.next()?.parse::<i64>().ok()?,
+            "--vnodes" => options.vnodes = args.next()?.parse::<i64>().ok().filter(|&v| v > 0)?,
+            "--proximity" => options.proximity = on_off(&args.next()?)?,
+            "--redirect" => options.redirect = on_off(&args.next()?)?,
+            _ => positional.push(arg),
+        }
+    }
+    Some((positional, options))
+}
+
+pub fn get_args() -> Option<(Param, Options)> {
+    let (args, options) = take_options(args().collect())?;
     let args = args.as_slice();
-    match args.len() {
-        4 => match (
+    let param: Option<Param> = match args.len() {
+        4 | 5 => match (
             args[1].parse::<Ipv4Addr>(),
             args[2].parse::<i64>(),
             args[3].parse::<i64>(),
         ) {
-            (Ok(ip), Ok(port), Ok(id)) => Some(Param::Short { ip, port, id }),
+            (Ok(ip), Ok(port), Ok(id)) => Some(Param::Short {
+                ip,
+                port,
+                id,
+                data_file: args.get(4).map(PathBuf::from),
+            }),
             _ => None,
         },
-        6 => {
+        6 | 7 => {
             match (
                 args[1].parse::<Ipv4Addr>(),
                 args[2].parse::<i64>(),
@@ -47,10 +96,12 @@ pub fn get_args() -> Option<Param> {
                     id_local,
                     ip_destination,
                     port_destination,
+                    data_file: args.get(6).map(PathBuf::from),
                 }),
                 _ => None,
             }
         }
         _ => None,
-    }
+    };
+    param.map(|p| (p, options))
 }
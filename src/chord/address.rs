@@ -1,8 +1,10 @@
 use serde_json::{json, Value};
 use std::io::prelude::*;
-use std::net::{Ipv4Addr, TcpStream};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
 
 use crate::chord::message::Message;
+use crate::chord::ring::RingId;
 #[derive(Debug, Clone)]
 pub struct Address {
     ip: Ipv4Addr,
@@ -12,30 +14,54 @@ pub struct Address {
 
 impl Address {
     pub fn new(ip: Ipv4Addr, port: i64, id: i64) -> Address {
-        return Address { ip, port, id };
+        Address { ip, port, id }
     }
 
     pub fn get_id(&self) -> i64 {
-        return self.id;
+        self.id
+    }
+
+    pub fn get_ring_id(&self) -> RingId {
+        RingId::new(self.id)
     }
 
     pub fn get_ip(&self) -> Ipv4Addr {
-        return self.ip;
+        self.ip
     }
 
     pub fn get_port(&self) -> i64 {
-        return self.port;
+        self.port
     }
 
     fn connect(&self) -> Option<TcpStream> {
         TcpStream::connect(format!("{}:{}", self.ip, self.port)).ok()
     }
 
+    /// Time taken to open a connection to the server at this address, about one round trip,
+    /// `None` when it does not answer within `timeout`. Nothing is sent on the connection.
+    pub fn probe(&self, timeout: Duration) -> Option<Duration> {
+        let target: SocketAddr = SocketAddr::from((self.ip, self.port as u16));
+        let start: Instant = Instant::now();
+        TcpStream::connect_timeout(&target, timeout).ok()?;
+        Some(start.elapsed())
+    }
+
+    /// Sends `mess` to the server at this address, tagged with the id of the (virtual) node
+    /// it is meant for.
     pub fn send_message(&self, mess: Message) -> Option<usize> {
-        let str_mess: String = mess.to_json().to_string();
+        self.send_json(mess.to_json())
+    }
+
+    /// Sends a message already in its json form, used to pass a received message on as is.
+    pub fn send_json(&self, mut json_mess: Value) -> Option<usize> {
+        json_mess["to"] = json!(self.id);
+        let str_mess: String = json_mess.to_string();
         println!("I'm sending the message {} to {:?}", str_mess, self);
         match self.connect() {
-            Some(mut s) => s.write(str_mess.as_bytes()).ok(),
+            Some(mut s) => s
+                .write_all(str_mess.as_bytes())
+                .ok()
+                .map(|_| str_mess.len()),
             _ => None,
         }
     }
@@ -0,0 +1,67 @@
+use crate::chord::address::Address;
+use crate::chord::ring::{Interval, RingId};
+
+/// Number of key ranges a location cache remembers.
+pub const LOCATION_CACHE_SIZE: usize = 64;
+
+/// The owners of the key ranges looked up lately, used to send a request straight to the
+/// owner of its key. The range owned by a node is `(previous, owner]`, a stale range is
+/// forgotten when its node answers that the key is not its own anymore.
+#[derive(Debug)]
+pub struct LocationCache {
+    capacity: usize,
+    /// the least recently used range first
+    ranges: Vec<(Interval, Address)>,
+}
+
+impl LocationCache {
+    pub fn new(capacity: usize) -> LocationCache {
+        LocationCache {
+            capacity,
+            ranges: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Owner of `key` if a cached range holds it, the range becomes the most recently used.
+    pub fn get(&mut self, key: i64) -> Option<Address> {
+        let position: RingId = RingId::new(key);
+        let idx: usize = self
+            .ranges
+            .iter()
+            .position(|(range, _)| range.contains(position))?;
+        let used: (Interval, Address) = self.ranges.remove(idx);
+        let owner: Address = used.1.clone();
+        self.ranges.push(used);
+        Some(owner)
+    }
+
+    /// Remembers that `owner` holds the keys after `previous` up to its id. The ranges it
+    /// overlaps are out of date and forgotten, the least recently used one goes when full.
+    pub fn insert(&mut self, previous: i64, owner: Address) {
+        if self.capacity == 0 {
+            return;
+        }
+        let range: Interval = Interval::open_closed(RingId::new(previous), owner.get_ring_id());
+        self.ranges.retain(|(other, a)| {
+            !range.contains(a.get_ring_id()) && !other.contains(owner.get_ring_id())
+        });
+        if self.ranges.len() >= self.capacity {
+            self.ranges.remove(0);
+        }
+        self.ranges.push((range, owner));
+    }
+
+    /// Forgets the range holding `key`.
+    pub fn invalidate(&mut self, key: i64) {
+        let position: RingId = RingId::new(key);
+        self.ranges.retain(|(range, _)| !range.contains(position));
+    }
+}
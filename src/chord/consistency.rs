@@ -0,0 +1,50 @@
+use serde_json::{json, Value};
+
+/// How many copies of a key (the owner and its replicas) must answer a `Get` or a `Put`
+/// before the owner replies to the requester.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Consistency {
+    One,
+    Quorum,
+    All,
+}
+
+impl Consistency {
+    /// Number of answers needed out of `copies` copies of a key.
+    pub fn required(&self, copies: i64) -> i64 {
+        match self {
+            Consistency::One => 1,
+            Consistency::Quorum => copies / 2 + 1,
+            Consistency::All => copies,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Consistency::One => "one",
+            Consistency::Quorum => "quorum",
+            Consistency::All => "all",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Consistency> {
+        match s.to_lowercase().as_str() {
+            "one" => Some(Consistency::One),
+            "quorum" => Some(Consistency::Quorum),
+            "all" => Some(Consistency::All),
+            _ => None,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!(self.as_str())
+    }
+
+    /// A missing or unknown level is read as `One`, the behaviour of nodes without replicas.
+    pub fn from_json(json_obj: &Value) -> Consistency {
+        json_obj
+            .as_str()
+            .and_then(Consistency::parse)
+            .unwrap_or(Consistency::One)
+    }
+}
@@ -0,0 +1,163 @@
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Timestamp of a write, tie-broken by the id of the node that accepted it. The counter is a
+/// Lamport clock kept at least at the wall clock in milliseconds, so that the writes of nodes
+/// whose clocks advanced unevenly still order by the time they were made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Version {
+    counter: i64,
+    writer: i64,
+}
+
+impl Version {
+    pub fn new(counter: i64, writer: i64) -> Version {
+        Version { counter, writer }
+    }
+
+    pub fn get_counter(&self) -> i64 {
+        self.counter
+    }
+
+    pub fn get_writer(&self) -> i64 {
+        self.writer
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({"counter" : self.counter, "writer" : self.writer})
+    }
+
+    pub fn from_json(json_obj: &Value) -> Option<Version> {
+        match (json_obj["counter"].as_i64(), json_obj["writer"].as_i64()) {
+            (Some(counter), Some(writer)) => Some(Version::new(counter, writer)),
+            _ => None,
+        }
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.counter
+            .cmp(&other.counter)
+            .then(self.writer.cmp(&other.writer))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Milliseconds elapsed since the unix epoch, used as the reference for entry expiry.
+pub fn now_millis() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as i64,
+        Err(_) => 0,
+    }
+}
+
+/// Instant, in unix milliseconds, after which a write made at `now` with a TTL of `ttl` seconds
+/// is expired. A TTL too large to be counted in milliseconds never expires.
+pub fn expiry(now: i64, ttl: i64) -> i64 {
+    ttl.checked_mul(1000)
+        .map_or(i64::MAX, |ms| now.saturating_add(ms))
+}
+
+/// A stored value together with the version of the write that produced it and, when it was
+/// written with a TTL, the instant (in unix milliseconds) after which it is expired.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    value: f64,
+    version: Version,
+    expires_at: Option<i64>,
+}
+
+impl Entry {
+    pub fn new(value: f64, version: Version, expires_at: Option<i64>) -> Entry {
+        Entry {
+            value,
+            version,
+            expires_at,
+        }
+    }
+
+    pub fn get_value(&self) -> f64 {
+        self.value
+    }
+
+    pub fn get_version(&self) -> Version {
+        self.version
+    }
+
+    pub fn get_expires_at(&self) -> Option<i64> {
+        self.expires_at
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        match self.expires_at {
+            Some(expires_at) => expires_at <= now,
+            None => false,
+        }
+    }
+
+    pub fn is_newer_than(&self, other: &Entry) -> bool {
+        self.version > other.version
+    }
+
+    pub fn to_json(&self, key: i64) -> Value {
+        json!({"key" : key, "value" : self.value, "version" : self.version.to_json(), "expires_at" : self.expires_at})
+    }
+
+    pub fn from_json(json_obj: &Value) -> Option<(i64, Entry)> {
+        match (
+            json_obj["key"].as_i64(),
+            json_obj["value"].as_f64(),
+            Version::from_json(&json_obj["version"]),
+        ) {
+            (Some(key), Some(value), Some(version)) => Some((
+                key,
+                Entry::new(value, version, json_obj["expires_at"].as_i64()),
+            )),
+            _ => None,
+        }
+    }
+}
+
+pub fn data_to_json(data: &HashMap<i64, Entry>) -> Value {
+    Value::Array(
+        data.iter()
+            .map(|(&key, entry)| entry.to_json(key))
+            .collect(),
+    )
+}
+
+pub fn data_from_json(json_obj: &Value) -> HashMap<i64, Entry> {
+    match json_obj.as_array() {
+        Some(entries) => entries.iter().filter_map(Entry::from_json).collect(),
+        None => HashMap::new(),
+    }
+}
+
+/// Reads an export, one json entry per line, back into `MultiPut` entries. Each entry keeps the
+/// TTL it had left at `now`, rounded up to the second, and expired entries are skipped.
+pub fn import_entries(content: &str, now: i64) -> Option<Vec<Value>> {
+    let mut entries: Vec<Value> = Vec::new();
+    for line in content.lines().filter(|l| !l.trim().is_empty()) {
+        let e: Value = serde_json::from_str(line).ok()?;
+        let ttl: Option<i64> = match e["expires_at"].as_i64() {
+            Some(expires_at) if expires_at <= now => continue,
+            Some(expires_at) => Some((expires_at - now + 999) / 1000),
+            None => None,
+        };
+        match (e["key"].as_i64(), e["value"].as_f64()) {
+            (Some(key), Some(value)) => {
+                entries.push(json!({"key" : key, "value" : value, "ttl" : ttl}))
+            }
+            _ => return None,
+        }
+    }
+    Some(entries)
+}
@@ -0,0 +1,160 @@
+use crate::chord::address::Address;
+use crate::chord::message::Message::{FindNext, GetResp};
+use crate::chord::node::{get_addr_from_json, read_parse};
+use crate::chord::route::Route;
+use serde_json::Value;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, TcpListener};
+use std::time::{Duration, Instant};
+
+/// Number of nodes an iterative lookup asks before giving up.
+const MAX_LOOKUP_HOPS: usize = 64;
+
+/// Where an iterative lookup ended: the owner of the key and the nodes that answered on the
+/// way, in the order they were asked.
+#[derive(Debug, Clone)]
+pub struct Lookup {
+    owner: Address,
+    path: Vec<Address>,
+}
+
+impl Lookup {
+    pub fn get_owner(&self) -> Address {
+        self.owner.clone()
+    }
+
+    pub fn get_path(&self) -> Vec<Address> {
+        self.path.clone()
+    }
+}
+
+/// Where a key lives and how many times the request was forwarded before reaching its owner.
+#[derive(Debug, Clone)]
+pub struct Location {
+    owner: Address,
+    /// id of the owner's previous node, the owner holds the keys after it
+    previous: i64,
+    route: Route,
+}
+
+impl Location {
+    pub fn get_owner(&self) -> Address {
+        self.owner.clone()
+    }
+
+    pub fn get_previous(&self) -> i64 {
+        self.previous
+    }
+
+    pub fn get_hops(&self) -> i64 {
+        self.route.get_hops()
+    }
+
+    /// Ids of the nodes the request went through, the owner last.
+    pub fn get_trace(&self) -> Vec<i64> {
+        self.route.get_trace().unwrap_or_default()
+    }
+}
+
+/// Waits at most `timeout` for the `cmd` answer about `key`, other messages are dropped.
+fn receive(sock: &TcpListener, cmd: &str, key: i64, timeout: Duration) -> Option<Value> {
+    let deadline: Instant = Instant::now() + timeout;
+    loop {
+        match sock.accept() {
+            Ok((s, _)) => {
+                if s.set_nonblocking(false).is_err() {
+                    continue;
+                }
+                if let Some(v) = read_parse(s) {
+                    if v["cmd"] == cmd && v["args"]["key"].as_i64() == Some(key) {
+                        return Some(v["args"].to_owned());
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return None;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Err(_) => return None,
+        }
+    }
+}
+
+/// Opens a listener on `local_ip` for the answers of a lookup, with the address to give the
+/// nodes.
+fn reply_listener(local_ip: Ipv4Addr) -> Option<(TcpListener, Address)> {
+    let sock: TcpListener = TcpListener::bind((local_ip, 0)).ok()?;
+    sock.set_nonblocking(true).ok()?;
+    let local: Address = Address::new(local_ip, sock.local_addr().ok()?.port() as i64, -1);
+    Some((sock, local))
+}
+
+/// Finds the owner of `key` by letting `entry` route the request through the ring, the owner
+/// answers with its address and the number of hops it took. `None` when no answer came back
+/// within `timeout`.
+pub fn locate(
+    entry: &Address,
+    local_ip: Ipv4Addr,
+    key: i64,
+    timeout: Duration,
+) -> Option<Location> {
+    let (sock, local): (TcpListener, Address) = reply_listener(local_ip)?;
+    entry.send_message(GetResp(local, key, Route::new(true)))?;
+    let answer: Value = receive(&sock, "answer_resp", key, timeout)?;
+    Some(Location {
+        owner: get_addr_from_json(&answer, "address")?,
+        previous: answer["previous"].as_i64()?,
+        route: Route::from_json(&answer["route"]),
+    })
+}
+
+/// Finds the owner of `key` by asking the nodes one after the other, starting from `start`,
+/// instead of letting them forward the request. A hop which can't be reached or does not
+/// answer within `hop_timeout` is skipped and the other fingers of the previous hop are tried.
+/// The answers come back on a listener opened on `local_ip` for the time of the lookup.
+pub fn lookup_iterative(
+    start: &Address,
+    local_ip: Ipv4Addr,
+    key: i64,
+    hop_timeout: Duration,
+) -> Option<Lookup> {
+    let (sock, local): (TcpListener, Address) = reply_listener(local_ip)?;
+    let mut path: Vec<Address> = Vec::new();
+    let mut asked: Vec<Address> = Vec::new();
+    // the next node to ask is the last one
+    let mut candidates: Vec<Address> = vec![start.clone()];
+    while let Some(hop) = candidates.pop() {
+        if asked.contains(&hop) {
+            continue;
+        }
+        if asked.len() >= MAX_LOOKUP_HOPS {
+            return None;
+        }
+        asked.push(hop.clone());
+        if hop.send_message(FindNext(local.clone(), key)).is_none() {
+            println!("LOOKUP : {:?} can't be reached", hop);
+            continue;
+        }
+        let answer: Value = match receive(&sock, "next_hop", key, hop_timeout) {
+            Some(v) => v,
+            None => {
+                println!("LOOKUP : {:?} does not answer", hop);
+                continue;
+            }
+        };
+        path.push(get_addr_from_json(&answer, "address").unwrap_or(hop));
+        if let Some(owner) = get_addr_from_json(&answer, "owner") {
+            return Some(Lookup { owner, path });
+        }
+        if let Some(next) = answer["candidates"].as_array() {
+            let next: Vec<Address> = next
+                .iter()
+                .filter_map(|c| get_addr_from_json(c, "address"))
+                .collect();
+            candidates.extend(next.into_iter().rev());
+        }
+    }
+    None
+}
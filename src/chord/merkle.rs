@@ -0,0 +1,107 @@
+use crate::chord::entry::Entry;
+use crate::chord::node::{key_position, MAX_NODE};
+use serde_json::{json, Value};
+
+/// Merkle tree over the ring positions: leaf `p` hashes the entries whose key sits at
+/// position `p`, every inner node hashes its two children. The tree is stored as a heap,
+/// node `i` having `2i + 1` and `2i + 2` as children, so the leaves are the last
+/// `MAX_NODE` nodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerkleTree {
+    nodes: Vec<u64>,
+}
+
+/// FNV-1a offset basis and prime. The hash has no random seed and goes over the bytes in
+/// little-endian order, so every node and every build computes the same trees.
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+fn fnv(words: &[u64]) -> u64 {
+    let mut hash: u64 = FNV_OFFSET;
+    for word in words {
+        for byte in word.to_le_bytes().iter() {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    hash
+}
+
+fn hash_entry(key: i64, entry: &Entry) -> u64 {
+    let (has_expiry, expires_at): (u64, i64) = match entry.get_expires_at() {
+        Some(t) => (1, t),
+        None => (0, 0),
+    };
+    fnv(&[
+        key as u64,
+        entry.get_value().to_bits(),
+        entry.get_version().get_counter() as u64,
+        entry.get_version().get_writer() as u64,
+        has_expiry,
+        expires_at as u64,
+    ])
+}
+
+fn hash_children(left: u64, right: u64) -> u64 {
+    fnv(&[left, right])
+}
+
+impl MerkleTree {
+    pub fn build<I>(entries: I) -> MerkleTree
+    where
+        I: Iterator<Item = (i64, Entry)>,
+    {
+        let leaves: usize = MAX_NODE as usize;
+        let mut nodes: Vec<u64> = vec![0; 2 * leaves - 1];
+        // leaves combine their entries with a xor so the order they come in does not matter
+        for (key, entry) in entries {
+            nodes[leaves - 1 + key_position(key) as usize] ^= hash_entry(key, &entry);
+        }
+        for i in (0..leaves - 1).rev() {
+            nodes[i] = hash_children(nodes[2 * i + 1], nodes[2 * i + 2]);
+        }
+        MerkleTree { nodes }
+    }
+
+    pub fn root(&self) -> u64 {
+        self.nodes[0]
+    }
+
+    /// Ring positions whose entries differ between the two trees, found by only walking down
+    /// the subtrees whose hashes differ.
+    pub fn diff(&self, other: &MerkleTree) -> Vec<i64> {
+        let leaves: usize = MAX_NODE as usize;
+        let mut positions: Vec<i64> = Vec::new();
+        let mut to_visit: Vec<usize> = vec![0];
+        while let Some(i) = to_visit.pop() {
+            if self.nodes[i] == other.nodes[i] {
+                continue;
+            }
+            if i >= leaves - 1 {
+                positions.push((i - (leaves - 1)) as i64);
+            } else {
+                to_visit.push(2 * i + 1);
+                to_visit.push(2 * i + 2);
+            }
+        }
+        positions.sort_unstable();
+        positions
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!(self.nodes)
+    }
+
+    pub fn from_json(json_obj: &Value) -> Option<MerkleTree> {
+        let nodes: Vec<u64> = json_obj
+            .as_array()?
+            .iter()
+            .map(|n| n.as_u64())
+            .collect::<Option<Vec<u64>>>()?;
+        if nodes.len() == 2 * MAX_NODE as usize - 1 {
+            Some(MerkleTree { nodes })
+        } else {
+            None
+        }
+    }
+}
@@ -1,4 +1,7 @@
 use crate::chord::address::Address;
+use crate::chord::consistency::Consistency;
+use crate::chord::entry::Version;
+use crate::chord::route::Route;
 use serde_json::{json, Value};
 
 macro_rules! json_builder {
@@ -7,56 +10,210 @@ macro_rules! json_builder {
     };
 }
 
+#[derive(Clone)]
 pub enum Message {
-    Ack(i64),
-    Answer(i64, f64, bool),
-    AnswerResp(i64, Address),
+    Ack(i64, Route),
+    Answer(i64, f64, bool, Version, Option<Route>),
+    AnswerResp(i64, Address, i64, Route),
+    Dump(Address, Option<i64>, Route),
+    DumpPage(i64, Value, bool),
+    Error(i64, String),
     Exit(),
-    Put(Address, i64, f64, i64),
-    Get(Address, i64),
-    GetResp(Address, i64),
+    FindNext(Address, i64),
+    Put(Address, i64, f64, i64, Option<i64>, Consistency, Route),
+    Get(Address, i64, Consistency, Route),
+    GetResp(Address, i64, Route),
     GetStat(Address, i64, i64, i64),
-    Hello(Address),
+    Hello(Address, Route),
+    Hinted(Address, Value, Route),
     HelloKO(i64),
-    HelloOK(i64, Address, Value, Address),
+    HelloOK(i64, Address, Value, Address, Route),
+    Incr(Address, i64, f64, Route),
+    JoinCommit(Address, Address, Value),
+    JoinConfirm(Address),
+    Leave(Address, Address),
+    Move(Address, i64),
+    Moved(i64, Address, Address, Option<i64>, Option<Value>),
+    MultiAnswer(i64, Value),
+    MultiGet(Address, Value, i64, Route),
+    MultiPut(Address, Value, i64, Route),
+    NextHop(Address, i64, Option<Address>, Value),
     Print(Address),
+    Probed(Value),
+    Rebalance(Address, String, Value, Route),
+    Rebalanced(Value, Value),
+    Replace(Address, Address, i64),
+    Tick(),
+    Replicate(Address, Value, i64, Option<i64>),
+    ReplicaAck(Address, i64),
+    ReplicaRead(Address, i64, i64, i64),
+    ReplicaValue(Address, i64, Value),
+    Scan(Address, i64, i64, Option<i64>, Route),
+    ScanPage(i64, Value, bool),
+    SyncDiff(Address, Value, Value),
+    SyncRepair(Address, Value, Value),
+    SyncTree(Address, i64, Value, i64),
+    Transfer(Address, Value, i64),
+    TransferAck(Address, i64),
     UpdateTable(Address, i64, i64),
 }
 
 impl Message {
     pub fn to_json(&self) -> Value {
         match self {
-            Message::Ack(id) => json_builder!("ack", json!({ "id": id })),
-            Message::Answer(key, value, exists) => json_builder!(
+            Message::Ack(id, route) => {
+                json_builder!("ack", json!({ "id": id, "route" : route.to_json()}))
+            }
+            Message::Answer(key, value, exists, version, route) => json_builder!(
                 "answer",
-                json!({ "key" : key, "value" : value, "val_exists" : exists})
+                json!({ "key" : key, "value" : value, "value_exists" : exists, "version" : version.to_json(), "route" : route.as_ref().map(|r| r.to_json())})
             ),
-            Message::AnswerResp(key, addr) => json_builder!(
+            Message::AnswerResp(key, addr, previous, route) => json_builder!(
                 "answer_resp",
-                json!({ "key" : key, "address" : addr.to_json()})
+                json!({ "key" : key, "address" : addr.to_json(), "previous" : previous, "route" : route.to_json()})
             ),
-            Message::Exit() => json_builder!("exit", {}),
-            Message::Hello(addr) => json_builder!("hello", json!({ "address" : addr.to_json()})),
-            Message::Put(addr, key, value, id) => json_builder!(
-                "put",
-                json!({"address" : addr.to_json() ,"key" : key, "value" : value , "id" : id})
+            Message::Dump(addr, origin, route) => json_builder!(
+                "dump",
+                json!({"address" : addr.to_json(), "origin" : origin, "route" : route.to_json()})
             ),
-            Message::Get(addr, key) => {
-                json_builder!("get", json!({"address" : addr.to_json(), "key" : key}))
+            Message::DumpPage(id, entries, last) => json_builder!(
+                "dump_page",
+                json!({"id" : id, "entries" : entries, "last" : last})
+            ),
+            Message::Error(id, reason) => {
+                json_builder!("error", json!({"id" : id, "reason" : reason}))
             }
-            Message::GetResp(addr, key) => {
-                json_builder!("get_resp", json!({"address" : addr.to_json(), "key" : key}))
+            Message::Exit() => json_builder!("exit", {}),
+            Message::FindNext(addr, key) => {
+                json_builder!(
+                    "find_next",
+                    json!({"address" : addr.to_json(), "key" : key})
+                )
             }
+            Message::Hello(addr, route) => json_builder!(
+                "hello",
+                json!({ "address" : addr.to_json(), "route" : route.to_json()})
+            ),
+            Message::Hinted(addr, data, route) => json_builder!(
+                "hinted",
+                json!({"address" : addr.to_json(), "data" : data, "route" : route.to_json()})
+            ),
+            Message::Put(addr, key, value, id, ttl, consistency, route) => json_builder!(
+                "put",
+                json!({"address" : addr.to_json() ,"key" : key, "value" : value , "id" : id, "ttl" : ttl, "consistency" : consistency.to_json(), "route" : route.to_json()})
+            ),
+            Message::Get(addr, key, consistency, route) => json_builder!(
+                "get",
+                json!({"address" : addr.to_json(), "key" : key, "consistency" : consistency.to_json(), "route" : route.to_json()})
+            ),
+            Message::GetResp(addr, key, route) => json_builder!(
+                "get_resp",
+                json!({"address" : addr.to_json(), "key" : key, "route" : route.to_json()})
+            ),
             Message::GetStat(addr, get, put, gestion) => json_builder!(
                 "stats",
                 json!({"address" : addr.to_json(), "get_amt" : get, "put_amt" : put, "mgt_amt" : gestion  })
             ),
             Message::HelloKO(id) => json_builder!("hello_ko", json!({ "id": id })),
-            Message::HelloOK(id, addr_r, data, addr_p) => json_builder!(
+            Message::HelloOK(id, addr_r, data, addr_p, route) => json_builder!(
                 "hello_ok",
-                json!({"id" : id, "address_resp" : addr_r.to_json(), "data" : data , "address_previous" : addr_p.to_json()})
+                json!({"id" : id, "address_resp" : addr_r.to_json(), "data" : data , "address_previous" : addr_p.to_json(), "route" : route.to_json()})
+            ),
+            Message::Incr(addr, key, delta, route) => json_builder!(
+                "incr",
+                json!({"address" : addr.to_json(), "key" : key, "delta" : delta, "route" : route.to_json()})
+            ),
+            Message::JoinCommit(addr, previous, data) => json_builder!(
+                "join_commit",
+                json!({"address" : addr.to_json(), "previous" : previous.to_json(), "data" : data})
+            ),
+            Message::JoinConfirm(addr) => {
+                json_builder!("join_confirm", json!({"address" : addr.to_json()}))
+            }
+            Message::Leave(addr, previous) => json_builder!(
+                "leave",
+                json!({"address" : addr.to_json(), "previous" : previous.to_json()})
+            ),
+            Message::Move(hot, id) => {
+                json_builder!("move", json!({"address" : hot.to_json(), "id" : id}))
+            }
+            Message::Moved(key, addr, next, previous, request) => json_builder!(
+                "moved",
+                json!({"key" : key, "address" : addr.to_json(), "next" : next.to_json(), "previous" : previous, "request" : request})
+            ),
+            Message::MultiAnswer(id, results) => {
+                json_builder!("multi_answer", json!({"id" : id, "results" : results}))
+            }
+            Message::MultiGet(addr, keys, id, route) => json_builder!(
+                "multi_get",
+                json!({"address" : addr.to_json(), "keys" : keys, "id" : id, "route" : route.to_json()})
+            ),
+            Message::MultiPut(addr, entries, id, route) => json_builder!(
+                "multi_put",
+                json!({"address" : addr.to_json(), "entries" : entries, "id" : id, "route" : route.to_json()})
+            ),
+            Message::NextHop(addr, key, owner, candidates) => json_builder!(
+                "next_hop",
+                json!({"address" : addr.to_json(), "key" : key, "owner" : owner.as_ref().map(|o| o.to_json()), "candidates" : candidates})
             ),
             Message::Print(addr) => json_builder!("print", json!({"address" : addr.to_json()})),
+            Message::Probed(results) => json_builder!("probed", json!({"results" : results})),
+            Message::Rebalance(addr, metric, reports, route) => json_builder!(
+                "rebalance",
+                json!({"address" : addr.to_json(), "metric" : metric, "reports" : reports, "route" : route.to_json()})
+            ),
+            Message::Rebalanced(reports, moved) => {
+                json_builder!("rebalanced", json!({"reports" : reports, "moved" : moved}))
+            }
+            Message::Replace(old, new, origin) => json_builder!(
+                "replace",
+                json!({"old" : old.to_json(), "new" : new.to_json(), "origin" : origin})
+            ),
+            Message::Tick() => json_builder!("tick", {}),
+            Message::Replicate(addr, data, hops, request) => json_builder!(
+                "replicate",
+                json!({"address" : addr.to_json(), "data" : data, "hops" : hops, "request" : request})
+            ),
+            Message::ReplicaAck(addr, request) => json_builder!(
+                "replica_ack",
+                json!({"address" : addr.to_json(), "request" : request})
+            ),
+            Message::ReplicaRead(addr, request, key, hops) => json_builder!(
+                "replica_read",
+                json!({"address" : addr.to_json(), "request" : request, "key" : key, "hops" : hops})
+            ),
+            Message::ReplicaValue(addr, request, entry) => json_builder!(
+                "replica_value",
+                json!({"address" : addr.to_json(), "request" : request, "entry" : entry})
+            ),
+            Message::Scan(addr, start, end, origin, route) => json_builder!(
+                "scan",
+                json!({"address" : addr.to_json(), "start" : start, "end" : end, "origin" : origin, "route" : route.to_json()})
+            ),
+            Message::ScanPage(id, entries, last) => json_builder!(
+                "scan_page",
+                json!({"id" : id, "entries" : entries, "last" : last})
+            ),
+            Message::Transfer(addr, data, seq) => json_builder!(
+                "transfer",
+                json!({"address" : addr.to_json(), "data" : data, "seq" : seq})
+            ),
+            Message::TransferAck(addr, seq) => json_builder!(
+                "transfer_ack",
+                json!({"address" : addr.to_json(), "seq" : seq})
+            ),
+            Message::SyncDiff(addr, positions, data) => json_builder!(
+                "sync_diff",
+                json!({"address" : addr.to_json(), "positions" : positions, "data" : data})
+            ),
+            Message::SyncRepair(addr, positions, data) => json_builder!(
+                "sync_repair",
+                json!({"address" : addr.to_json(), "positions" : positions, "data" : data})
+            ),
+            Message::SyncTree(addr, lower, tree, hops) => json_builder!(
+                "sync_tree",
+                json!({"address" : addr.to_json(), "lower" : lower, "tree" : tree, "hops" : hops})
+            ),
             Message::UpdateTable(addr, low_key, amount) => json_builder!(
                 "update_table",
                 json!({"address" : addr.to_json(), "id_lower_key" : low_key , "amount" : amount})
@@ -1,3 +1,12 @@
 pub mod address;
+pub mod cache;
+pub mod consistency;
+pub mod entry;
+pub mod lookup;
+pub mod merkle;
 pub mod message;
 pub mod node;
+pub mod ring;
+pub mod route;
+pub mod state;
+pub mod storage;
@@ -1,30 +1,305 @@
 use crate::chord::address::Address;
+use crate::chord::cache::{LocationCache, LOCATION_CACHE_SIZE};
+use crate::chord::consistency::Consistency;
+use crate::chord::entry::{data_from_json, data_to_json, expiry, now_millis, Entry, Version};
+use crate::chord::merkle::MerkleTree;
+use crate::chord::message::Message;
 use crate::chord::message::Message::{
-    Ack, Answer, AnswerResp, Exit, Get, GetResp, GetStat, Hello, HelloKO, HelloOK, Print, Put,
-    UpdateTable,
+    Ack, Answer, AnswerResp, Dump, DumpPage, Error, Exit, Get, GetResp, GetStat, Hello, HelloKO,
+    HelloOK, Hinted, Incr, JoinCommit, JoinConfirm, Leave, Move, Moved, MultiAnswer, MultiGet,
+    MultiPut, NextHop, Print, Probed, Put, Rebalance, Rebalanced, Replace, ReplicaAck, ReplicaRead,
+    ReplicaValue, Replicate, Scan, ScanPage, SyncDiff, SyncRepair, SyncTree, Tick, Transfer,
+    TransferAck, UpdateTable,
 };
-use serde_json::{json, Value};
+use crate::chord::ring::{Interval, RingId};
+use crate::chord::route::Route;
+use crate::chord::state::StateFile;
+use crate::chord::storage::Storage;
+use rand::Rng;
+use serde_json::{json, Map, Value};
 use std::collections::HashMap;
 use std::io::Read;
 use std::net::{AddrParseError, Ipv4Addr, TcpListener, TcpStream};
 use std::thread::JoinHandle;
+use std::time::Duration;
 
-const MAX_NODE: i64 = 32;
+pub const MAX_NODE: i64 = 32;
 const HALF_CIRCLE: i64 = 16;
+const SCAN_PAGE_SIZE: usize = 64;
+const TRANSFER_CHUNK_SIZE: usize = 64;
+const TRANSFER_RETRIES: i64 = 3;
+/// Ticks a joining node waits for its join to be committed before saying `Hello` again.
+const JOIN_RETRY_TICKS: i64 = 2;
+const MAX_HINTS: usize = 1024;
+const HINT_TTL: i64 = 3_600_000;
+const REQUEST_TIMEOUT: i64 = 5_000;
+/// Why a write with a TTL of zero or less is refused: it would expire at once.
+const TTL_REFUSED: &str = "the ttl must be a positive number of seconds";
+/// Why a write needing several copies fails when its owner is unreachable: the write is only
+/// kept as a hint, to be replayed once the owner answers again.
+const HINTED_ONLY: &str = "the owner is unreachable, the write is only kept as a hint";
+/// A node is only moved next to a node at least this many times more loaded.
+const REBALANCE_FACTOR: i64 = 2;
+/// Requests a moving node passes to its old successor until it has joined at its new id.
+const MOVING_FORWARDED: [&str; 12] = [
+    "find_next",
+    "get",
+    "put",
+    "incr",
+    "hinted",
+    "multi_put",
+    "multi_get",
+    "scan",
+    "dump",
+    "get_resp",
+    "hello",
+    "rebalance",
+];
+const TICK_INTERVAL: Duration = Duration::from_secs(10);
+/// How far, in milliseconds, the clock may go before its bound is saved again.
+const CLOCK_RESERVE: i64 = 60_000;
+/// How long reading a message may take.
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a node measuring its round trip times waits for each neighbour.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(200);
+/// Neighbours measured at most per tick, so that a round always ends well before the next one.
+const PROBES_PER_TICK: usize = 4;
+/// How long, in milliseconds, a round trip time is used before it is measured again.
+const RTT_MAX_AGE: i64 = 60_000;
 
+/// A key range being handed over to a joining node, reserved for it until the handoff is
+/// committed or given up: any other node joining meanwhile waits. The keys are streamed in
+/// chunks, each one acknowledged by the joiner, then the `HelloOK` asks the joiner to confirm,
+/// and the range only changes hands once it did. A node moving hands its whole range to its
+/// successor the same way before it leaves.
 #[derive(Debug)]
-pub struct Node {
+struct Handoff {
+    receiver: Address,
+    keys: Vec<i64>,
+    sent: HashMap<i64, Version>,
+    next: usize,
+    /// whether the receiver acknowledged the first chunk, sent even for an empty range so that
+    /// it drops what a handoff given up before gave it
+    opened: bool,
+    retries: i64,
+    /// route of the joiner's `Hello`, given back with the `HelloOK`
+    route: Route,
+    /// the node next to which I join again and the id I take, when I am the one leaving
+    move_to: Option<(Address, i64)>,
+}
+
+/// My own join, from my first `Hello` until my successor commits it.
+#[derive(Debug)]
+struct Joining {
+    entry: Address,
+    /// my previous node as told by the `HelloOK`
+    previous: Option<Address>,
+    ticks: i64,
+    /// versions of the entries handed over to me so far, dropped if the handoff is given up
+    received: HashMap<i64, Version>,
+}
+
+/// A write whose owner could not be reached, kept to be replayed once it answers again. The
+/// message is kept in its json form, as it is saved with the state.
+#[derive(Debug)]
+struct Hint {
+    target: Address,
+    message: Value,
+    expires_at: i64,
+}
+
+/// A read or a write of the owner waiting for enough copies of the key to answer. A read stays
+/// here once answered so the copies answering late can still be repaired.
+#[derive(Debug)]
+struct Pending {
+    requester: Address,
+    key: i64,
+    /// id of the requester's `Put`, `None` for a `Get`
+    write_id: Option<i64>,
+    needed: i64,
+    answers: Vec<(Address, Option<Entry>)>,
+    repaired: usize,
+    done: bool,
+    started: i64,
+    route: Route,
+}
+
+/// A node met while routing, a candidate finger when proximity is on.
+#[derive(Debug)]
+struct Neighbour {
+    addr: Address,
+    /// smoothed round trip time in microseconds, `None` until first measured
+    rtt: Option<i64>,
+    /// when it was last measured, 0 if never
+    measured: i64,
+}
+
+#[derive(Debug)]
+pub struct Node<S: Storage> {
     previous: Address,
     association: HashMap<i64, Address>,
-    data: HashMap<i64, f64>,
+    data: S,
     addr: Address,
+    clock: i64,
+    /// bound of the clock in the saved state, no version above it was given yet
+    clock_saved: i64,
+    state: Option<StateFile>,
+    handoff: Option<Handoff>,
+    waiting: Vec<(Address, Route)>,
+    replicas: i64,
+    replica_data: HashMap<i64, Entry>,
+    hints: Vec<Hint>,
+    requests: HashMap<i64, Pending>,
+    next_request: i64,
+    /// my old successor while I move to a new id, it took over my keys
+    moving: Option<Address>,
+    joining: Option<Joining>,
+    reported_requests: i64,
+    /// whether each finger is the closest node of its slot in round trip time
+    proximity: bool,
+    neighbours: HashMap<i64, Neighbour>,
+    /// owners of the key ranges I looked up, to pass requests straight to them
+    cache: LocationCache,
+    /// whether a request for a key I do not own goes back to its requester instead of on
+    redirect: bool,
     put: i64,
     get: i64,
     mgt: i64,
     exit: bool,
 }
 
-fn get_addr_from_json(json_obj: &Value, fields: &str) -> Option<Address> {
+/// Position of `key` on the ring.
+pub fn key_position(key: i64) -> i64 {
+    let position: RingId = RingId::new(key);
+    position.get()
+}
+
+/// Finger table of the node `id` with every finger pointing to `addr`.
+fn fingers(id: i64, addr: &Address) -> HashMap<i64, Address> {
+    let start: RingId = RingId::new(id);
+    let mut association: HashMap<i64, Address> = HashMap::new();
+    let mut idx: i64 = 1;
+    while idx <= HALF_CIRCLE {
+        association.insert((start + idx).get(), addr.clone());
+        idx *= 2;
+    }
+    association
+}
+
+/// Results of a batch of `writes` whose owner was unreachable, for the requester: the version
+/// of those kept as hints, the others are refused by their ttl.
+fn hinted_results(writes: &[(i64, f64, Option<i64>)], kept: &HashMap<i64, Version>) -> Value {
+    let mut results: Map<String, Value> = Map::new();
+    for &(key, _, _) in writes {
+        let result: Value = match kept.get(&key) {
+            Some(version) => json!({"version" : version.to_json(), "hinted" : true}),
+            None => json!({ "error": TTL_REFUSED }),
+        };
+        results.insert(key.to_string(), result);
+    }
+    Value::Object(results)
+}
+
+/// Picks, among the load reports of a rebalance walk, the least loaded node and the position
+/// it should move to in the interval of the most loaded one.
+pub fn plan_move(reports: &[Value]) -> Option<(Address, Address, i64)> {
+    let loaded: Vec<(Address, i64, Option<i64>)> = reports
+        .iter()
+        .filter_map(|r| {
+            Some((
+                get_addr_from_json(r, "address")?,
+                r["load"].as_i64()?,
+                r["split"].as_i64(),
+            ))
+        })
+        .collect();
+    let (hot, hot_load, split) = loaded.iter().max_by_key(|(_, load, _)| *load)?;
+    let (cold, cold_load, _) = loaded
+        .iter()
+        .filter(|(a, _, _)| a != hot)
+        .min_by_key(|(_, load, _)| *load)?;
+    if *hot_load > REBALANCE_FACTOR * *cold_load {
+        split.map(|split| (cold.clone(), hot.clone(), split))
+    } else {
+        None
+    }
+}
+
+/// Position in the interval `(previous, id]` that splits the weighted keys in it in two
+/// halves. A node joining there takes the lower half.
+pub fn split_position(previous: i64, id: i64, weights: &[(i64, i64)]) -> Option<i64> {
+    let previous: RingId = RingId::new(previous);
+    let width: i64 = Interval::open_closed(previous, RingId::new(id)).width();
+    let mut offsets: Vec<(i64, i64)> = weights
+        .iter()
+        .map(|&(k, weight)| {
+            let offset: i64 = match previous.distance(RingId::new(k)) {
+                0 => MAX_NODE,
+                o => o,
+            };
+            (offset, weight)
+        })
+        .collect();
+    offsets.sort_unstable();
+    let total: i64 = offsets.iter().map(|(_, w)| w).sum();
+    if width < 2 || total == 0 {
+        return None;
+    }
+    let mut acc: i64 = 0;
+    for (offset, weight) in offsets {
+        acc += weight;
+        if 2 * acc >= total {
+            return Some((previous + offset.clamp(1, width - 1)).get());
+        }
+    }
+    None
+}
+
+/// Fingers of the node `me` lying between it and the ring position `id`, the closest to `id`
+/// first.
+pub fn closest_preceding(
+    me: &Address,
+    association: &HashMap<i64, Address>,
+    id: RingId,
+) -> Vec<Address> {
+    let ahead: Interval = Interval::open_closed(me.get_ring_id(), id);
+    let mut fingers: Vec<Address> = Vec::new();
+    for a in association.values() {
+        if a != me && ahead.contains(a.get_ring_id()) && !fingers.contains(a) {
+            fingers.push(a.clone());
+        }
+    }
+    fingers.sort_by_key(|a| a.get_ring_id().distance(id));
+    fingers
+}
+
+/// Next hop from the node `me`, whose interval is `(previous, me]`, towards the owner of the
+/// key `key`: `me` when it owns the key, its successor when the successor does, else the
+/// finger closest before the key, which at least halves the distance left.
+pub fn next_hop(
+    me: &Address,
+    previous: i64,
+    association: &HashMap<i64, Address>,
+    key: i64,
+) -> Option<Address> {
+    let id: RingId = RingId::new(key);
+    let mine: Interval = Interval::open_closed(RingId::new(previous), me.get_ring_id());
+    if mine.contains(id) {
+        return Some(me.clone());
+    }
+    let successor: Option<&Address> = association.get(&(me.get_ring_id() + 1).get());
+    match successor {
+        Some(s) if Interval::open_closed(me.get_ring_id(), s.get_ring_id()).contains(id) => {
+            Some(s.clone())
+        }
+        _ => closest_preceding(me, association, id)
+            .into_iter()
+            .next()
+            .or_else(|| successor.cloned()),
+    }
+}
+
+pub fn get_addr_from_json(json_obj: &Value, fields: &str) -> Option<Address> {
     let addr: Value = json_obj[fields].to_owned();
     if let Some(ip_str) = addr["ip"].as_str() {
         let ip: Result<Ipv4Addr, AddrParseError> = ip_str.parse::<Ipv4Addr>();
@@ -37,26 +312,59 @@ fn get_addr_from_json(json_obj: &Value, fields: &str) -> Option<Address> {
     }
 }
 
-fn read_parse(mut stream: TcpStream) -> Option<Value> {
-    let mut buffer: [u8; 512] = [0; 512];
-    let u: usize = match stream.read(&mut buffer) {
-        Ok(s) => s,
-        Err(_) => return None,
-    };
-    match serde_json::from_slice(&buffer[0..u]) {
-        Ok(v) => Some(v),
+/// Reads a whole message: the sender closes the connection once it is written. A sender which
+/// keeps it open is given up after `READ_TIMEOUT`, not to hold up the other messages.
+pub fn read_parse(mut stream: TcpStream) -> Option<Value> {
+    stream.set_read_timeout(Some(READ_TIMEOUT)).ok()?;
+    let mut buffer: Vec<u8> = Vec::new();
+    match stream.read_to_end(&mut buffer) {
+        Ok(_) => serde_json::from_slice(&buffer).ok(),
         Err(_) => None,
     }
 }
 
-pub fn listen(mut n: Node) -> Option<JoinHandle<()>> {
-    match TcpListener::bind(format!("{}:{}", n.addr.get_ip(), n.addr.get_port())) {
+pub fn listen<S: Storage + 'static>(n: Node<S>) -> Option<JoinHandle<()>> {
+    listen_virtual(vec![n])
+}
+
+/// Runs the virtual nodes of a server behind one listener, all of them over the same storage.
+/// Every message goes to the node whose id it is addressed to, or to the first one when the
+/// sender did not know the id (a client, a node joining the ring). The server stops once all
+/// of them got an `Exit`.
+pub fn listen_virtual<S: Storage + 'static>(mut nodes: Vec<Node<S>>) -> Option<JoinHandle<()>> {
+    let first: Address = nodes.first()?.get_addr();
+    match TcpListener::bind(format!("{}:{}", first.get_ip(), first.get_port())) {
         Ok(sock) => Some(std::thread::spawn(move || {
+            for n in nodes.iter() {
+                n.start_join();
+            }
+            std::thread::spawn(move || loop {
+                std::thread::sleep(TICK_INTERVAL);
+                if first.send_message(Tick()).is_none() {
+                    break;
+                }
+            });
             for stream in sock.incoming() {
                 if let Ok(s) = stream {
-                    n.handle_message(s);
-                    println!("{:?}", n);
-                    if n.exit {
+                    let v: Option<Value> = read_parse(s);
+                    if let Some(v) = v.as_ref().filter(|v| v["cmd"] == "tick") {
+                        // the ids of the virtual nodes can change, every one of them ticks
+                        for n in nodes.iter_mut() {
+                            n.handle_message(v.clone());
+                            n.save_state();
+                        }
+                    } else if let Some(v) = v {
+                        let to: Option<i64> = v["to"].as_i64();
+                        let idx: usize = nodes
+                            .iter()
+                            .position(|n| Some(n.addr.get_id()) == to)
+                            .unwrap_or(0);
+                        let n: &mut Node<S> = &mut nodes[idx];
+                        n.handle_message(v);
+                        n.save_state();
+                        println!("{:?}", n);
+                    }
+                    if nodes.iter().all(|n| n.exit) {
                         println!("exit");
                         break;
                     }
@@ -69,58 +377,219 @@ pub fn listen(mut n: Node) -> Option<JoinHandle<()>> {
     }
 }
 
-impl Node {
-    pub fn new(ip: Ipv4Addr, port: i64, id: i64) -> Node {
-        let id: i64 = id % MAX_NODE;
-        let addr: Address = Address::new(ip, port, id);
-        let mut n: Node = Node {
+impl<S: Storage> Node<S> {
+    pub fn new(ip: Ipv4Addr, port: i64, id: i64, data: S) -> Node<S> {
+        let id: RingId = RingId::new(id);
+        let addr: Address = Address::new(ip, port, id.get());
+        // the clock must stay ahead of the writes reloaded from a durable storage
+        let clock: i64 = data
+            .iter()
+            .map(|(_, e)| e.get_version().get_counter())
+            .max()
+            .unwrap_or(0);
+        Node {
             previous: addr.clone(),
-            association: HashMap::new(),
-            data: HashMap::new(),
+            association: fingers(id.get(), &addr),
+            data,
             addr: addr.clone(),
+            clock,
+            clock_saved: clock,
+            state: None,
+            handoff: None,
+            waiting: Vec::new(),
+            replicas: 0,
+            replica_data: HashMap::new(),
+            hints: Vec::new(),
+            requests: HashMap::new(),
+            next_request: 0,
+            moving: None,
+            joining: None,
+            reported_requests: 0,
+            proximity: false,
+            neighbours: HashMap::new(),
+            cache: LocationCache::new(LOCATION_CACHE_SIZE),
+            redirect: false,
             get: 0,
             put: 0,
             mgt: 0,
             exit: false,
-        };
-        let mut idx: i64 = 1;
-        while idx <= HALF_CIRCLE {
-            n.association
-                .insert((n.addr.get_id() + idx) % MAX_NODE, addr.clone());
-            idx *= 2;
         }
-        n
     }
     pub fn get_addr(&self) -> Address {
-        return self.addr.clone();
-    }
-
-    fn handle_message(&mut self, stream: TcpStream) {
-        if let Some(v) = read_parse(stream) {
-            if let Some(s) = v["cmd"].as_str() {
-                let args = v["args"].to_owned();
-                match s {
-                    "exit" => self.handle_exit(args),
-                    "ack" => self.handle_ack(args),
-                    "answer" => self.handle_answer(args),
-                    "answer_resp" => self.handle_answer_resp(args),
-                    "stats" => self.handle_get_stat(args),
-                    "print" => self.handle_print(args),
-                    "get" => self.handle_get(args),
-                    "get_resp" => self.handle_get_resp(args),
-                    "put" => self.handle_put(args),
-                    "hello" => self.handle_hello(args),
-                    "hello_ok" => self.handle_hello_ok(args),
-                    "hello_ko" => self.handle_hello_ko(args),
-                    "update_table" => self.handle_update_table(args),
-                    _ => {}
-                };
+        self.addr.clone()
+    }
+
+    /// Makes the next `replicas` successors keep a copy of every entry this node owns.
+    pub fn set_replicas(&mut self, replicas: i64) {
+        self.replicas = replicas.max(0);
+    }
+
+    /// Makes every finger point to the node of its slot with the lowest round trip time instead
+    /// of the first node after the finger key.
+    pub fn set_proximity(&mut self, proximity: bool) {
+        self.proximity = proximity;
+    }
+
+    /// Makes the `Get` and `Put` for keys I do not own go back to their requester with the
+    /// node to retry at, instead of being passed on.
+    pub fn set_redirect(&mut self, redirect: bool) {
+        self.redirect = redirect;
+    }
+
+    /// Makes the node join the ring through `entry` once it listens.
+    pub fn join(&mut self, entry: Address) {
+        self.joining = Some(Joining {
+            entry,
+            previous: None,
+            ticks: 0,
+            received: HashMap::new(),
+        });
+    }
+
+    fn start_join(&self) {
+        if let Some(j) = self.joining.as_ref() {
+            j.entry
+                .send_message(Hello(self.addr.clone(), Route::new(true)));
+        }
+    }
+
+    /// Restores the previous node, the finger table and the clock saved in `state` before the
+    /// node stopped, then keeps saving them there. Returns whether a saved
+    /// state was found, in which case the node should rejoin through its successor.
+    pub fn recover(&mut self, state: StateFile) -> bool {
+        let mut recovered: bool = false;
+        if let Some(v) = state.load() {
+            if let Some(addr) = get_addr_from_json(&v, "address") {
+                // the node was moved to another id by a rebalance
+                if addr.get_id() != self.addr.get_id() {
+                    self.addr = addr;
+                    self.association.clear();
+                }
+            }
+            if let Some(previous) = get_addr_from_json(&v, "previous") {
+                self.previous = previous;
+                recovered = true;
             }
+            if let Some(fingers) = v["association"].as_array() {
+                for f in fingers {
+                    if let (Some(key), Some(addr)) =
+                        (f["key"].as_i64(), get_addr_from_json(f, "address"))
+                    {
+                        self.association.insert(key, addr);
+                    }
+                }
+            }
+            // the writes kept for unreachable owners outlive a restart
+            if let Some(hints) = v["hints"].as_array() {
+                for h in hints {
+                    if let (Some(target), Some(expires_at)) =
+                        (get_addr_from_json(h, "target"), h["expires_at"].as_i64())
+                    {
+                        let message: Value = h["message"].to_owned();
+                        self.hints.push(Hint {
+                            target,
+                            message,
+                            expires_at,
+                        });
+                    }
+                }
+            }
+            // no version was given past the saved bound
+            self.clock = self.clock.max(v["clock"].as_i64().unwrap_or(0));
+        }
+        self.clock_saved = self.clock;
+        self.state = Some(state);
+        recovered
+    }
+
+    /// Saves the routing state and the hints when they changed. The clock is saved as a bound
+    /// reserved `CLOCK_RESERVE` ticks ahead, so that writes only cause a save once in a while.
+    fn save_state(&mut self) {
+        let state: &mut StateFile = match self.state.as_mut() {
+            Some(state) => state,
+            None => return,
+        };
+        if self.clock >= self.clock_saved {
+            self.clock_saved = self.clock + CLOCK_RESERVE;
+        }
+        let mut fingers: Vec<(&i64, &Address)> = self.association.iter().collect();
+        fingers.sort_unstable_by_key(|(key, _)| **key);
+        let fingers: Vec<Value> = fingers
+            .into_iter()
+            .map(|(key, addr)| json!({"key" : key, "address" : addr.to_json()}))
+            .collect();
+        let hints: Vec<Value> = self
+            .hints
+            .iter()
+            .map(|h| json!({"target" : h.target.to_json(), "message" : h.message, "expires_at" : h.expires_at}))
+            .collect();
+        let v: Value = json!({
+            "address" : self.addr.to_json(),
+            "previous" : self.previous.to_json(),
+            "association" : fingers,
+            "clock" : self.clock_saved,
+            "hints" : hints,
+        });
+        state.save(&v);
+    }
+
+    fn handle_message(&mut self, v: Value) {
+        if let Some(s) = v["cmd"].as_str() {
+            if let Some(successor) = self.moving.as_ref() {
+                if MOVING_FORWARDED.contains(&s) {
+                    successor.send_json(v.clone());
+                    return;
+                }
+            }
+            let args = v["args"].to_owned();
+            match s {
+                "exit" => self.handle_exit(args),
+                "ack" => self.handle_ack(args),
+                "error" => self.handle_error(args),
+                "answer" => self.handle_answer(args),
+                "answer_resp" => self.handle_answer_resp(args),
+                "stats" => self.handle_get_stat(args),
+                "print" => self.handle_print(args),
+                "probed" => self.handle_probed(args),
+                "tick" => self.handle_tick(args),
+                "get" => self.handle_get(args),
+                "get_resp" => self.handle_get_resp(args),
+                "find_next" => self.handle_find_next(args),
+                "put" => self.handle_put(args),
+                "incr" => self.handle_incr(args),
+                "hinted" => self.handle_hinted(args),
+                "rebalance" => self.handle_rebalance(args),
+                "move" => self.handle_move(args),
+                "moved" => self.handle_moved(args),
+                "leave" => self.handle_leave(args),
+                "replace" => self.handle_replace(args),
+                "multi_put" => self.handle_multi_put(args),
+                "multi_get" => self.handle_multi_get(args),
+                "scan" => self.handle_scan(args),
+                "dump" => self.handle_dump(args),
+                "hello" => self.handle_hello(args),
+                "hello_ok" => self.handle_hello_ok(args),
+                "hello_ko" => self.handle_hello_ko(args),
+                "join_confirm" => self.handle_join_confirm(args),
+                "join_commit" => self.handle_join_commit(args),
+                "transfer" => self.handle_transfer(args),
+                "replicate" => self.handle_replicate(args),
+                "replica_ack" => self.handle_replica_ack(args),
+                "replica_read" => self.handle_replica_read(args),
+                "replica_value" => self.handle_replica_value(args),
+                "sync_tree" => self.handle_sync_tree(args),
+                "sync_diff" => self.handle_sync_diff(args),
+                "sync_repair" => self.handle_sync_repair(args),
+                "transfer_ack" => self.handle_transfer_ack(args),
+                "update_table" => self.handle_update_table(args),
+                _ => {}
+            };
         }
     }
 
     fn handle_exit(&mut self, _v: Value) {
-        if self.addr.get_id() != self.previous.get_id() {
+        // a virtual node keeps listening for its siblings, it must not pass the exit on twice
+        if !self.exit && self.addr.get_id() != self.previous.get_id() {
             self.previous.send_message(Exit());
         }
         self.exit = true;
@@ -128,12 +597,22 @@ impl Node {
     fn handle_ack(&mut self, _args: Value) {
         // Do nothing on a node
     }
+    fn handle_error(&mut self, args: Value) {
+        if let (Some(id), Some(reason)) = (args["id"].as_i64(), args["reason"].as_str()) {
+            println!("request {} failed : {}", id, reason);
+        }
+    }
     fn handle_answer(&self, args: Value) {
         if let Some(key) = args["key"].as_i64() {
             if let Some(exists) = args["value_exists"].as_bool() {
                 if exists {
-                    if let Some(requested_value) = args["value"].as_f64() {
-                        println!("the value of key {} is {}", key, requested_value);
+                    if let (Some(requested_value), Some(version)) =
+                        (args["value"].as_f64(), Version::from_json(&args["version"]))
+                    {
+                        println!(
+                            "the value of key {} is {} (version {:?})",
+                            key, requested_value, version
+                        );
                     };
                 }
             }
@@ -142,7 +621,14 @@ impl Node {
     fn handle_answer_resp(&mut self, args: Value) {
         if let Some(addr) = get_addr_from_json(&args, "address") {
             if let Some(key) = args["key"].as_i64() {
-                self.association.insert(key, addr);
+                self.learn(&addr);
+                if let Some(previous) = args["previous"].as_i64() {
+                    self.cache.insert(previous, addr.clone());
+                }
+                // the other keys were only asked to discover the nodes of a finger slot
+                if self.association.contains_key(&key) {
+                    self.association.insert(key, addr);
+                }
             }
         }
     }
@@ -152,15 +638,52 @@ impl Node {
         if let Some(addr) = get_addr_from_json(&args, "address") {
             if let Some(id) = args["id"].as_i64() {
                 if let Some(key) = args["key"].as_i64() {
+                    let route: Route = Route::from_json(&args["route"]).through(self.addr.get_id());
                     if let Some(n) = self.find_resp_in_table(key) {
                         if let Some(v) = args["value"].as_f64() {
-                            if self.addr.get_id() == n.get_id() {
+                            let ttl: Option<i64> = args["ttl"].as_i64();
+                            let consistency: Consistency =
+                                Consistency::from_json(&args["consistency"]);
+                            if ttl.is_some_and(|t| t <= 0) {
+                                println!("PUT : refused, the ttl {:?} is not positive", ttl);
+                                addr.send_message(Error(id, TTL_REFUSED.to_string()));
+                            } else if self.addr.get_id() == n.get_id() {
                                 println!("PUT : I'm updating my data");
-                                self.data.insert(key, v);
-                                addr.send_message(Ack(id));
-                            } else {
-                                println!("PUT : Send the message to the next node");
-                                n.send_message(Put(addr, key, v, id));
+                                self.store(key, v, ttl);
+                                let needed: i64 = consistency.required(self.replicas + 1);
+                                if needed > 1 {
+                                    let request: i64 =
+                                        self.start_request(addr, key, Some(id), needed, route);
+                                    self.replicate(&[key], Some(request));
+                                } else {
+                                    self.replicate(&[key], None);
+                                    addr.send_message(Ack(id, route));
+                                }
+                            } else if let Some(next) = self.next_route(&route, "PUT", &addr, id) {
+                                let (n, next): (Address, Route) = self.via_cache(key, n, next);
+                                if self.redirect {
+                                    let retry: Route = next.cached_by(addr.clone());
+                                    let put: Message =
+                                        Put(addr.clone(), key, v, id, ttl, consistency, retry);
+                                    self.redirect_to(&addr, key, &n, put);
+                                } else {
+                                    println!("PUT : Send the message to the next node");
+                                    self.report_misdirected(&route, key, &n);
+                                    let put: Message =
+                                        Put(addr.clone(), key, v, id, ttl, consistency, next);
+                                    let kept: Option<HashMap<i64, Version>> =
+                                        self.forward_writes(&n, put, &[(key, v, ttl)]);
+                                    match kept {
+                                        None => {}
+                                        // the write is kept until its owner answers again
+                                        Some(_) if consistency == Consistency::One => {
+                                            addr.send_message(Ack(id, route));
+                                        }
+                                        Some(_) => {
+                                            addr.send_message(Error(id, HINTED_ONLY.to_string()));
+                                        }
+                                    }
+                                }
                             }
                         }
                     }
@@ -169,20 +692,109 @@ impl Node {
         }
     }
 
+    fn handle_incr(&mut self, args: Value) {
+        self.put += 1;
+        if let Some(addr) = get_addr_from_json(&args, "address") {
+            if let (Some(key), Some(delta)) = (args["key"].as_i64(), args["delta"].as_f64()) {
+                let route: Route = Route::from_json(&args["route"]).through(self.addr.get_id());
+                if let Some(n) = self.find_resp_in_table(key) {
+                    if self.addr.get_id() == n.get_id() {
+                        println!("INCR : I'm updating my data");
+                        let e: Entry = self.increment(key, delta);
+                        self.replicate(&[key], None);
+                        addr.send_message(Answer(
+                            key,
+                            e.get_value(),
+                            true,
+                            e.get_version(),
+                            Some(route),
+                        ));
+                    } else if let Some(next) = self.next_route(&route, "INCR", &addr, key) {
+                        println!("INCR : Send the message to the next node");
+                        self.report_misdirected(&route, key, &n);
+                        let (n, next): (Address, Route) = self.via_cache(key, n, next);
+                        self.forward(&n, Incr(addr, key, delta, next));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Merges the writes another node kept while their owner was unreachable. They carry the
+    /// version they were given then, a newer write of the same key wins over them. Those I do
+    /// not own anymore go on to their owner.
+    fn handle_hinted(&mut self, args: Value) {
+        if let Some(addr) = get_addr_from_json(&args, "address") {
+            let route: Route = Route::from_json(&args["route"]).through(self.addr.get_id());
+            let mut mine: Vec<i64> = Vec::new();
+            let mut others: HashMap<i64, (Address, HashMap<i64, Entry>)> = HashMap::new();
+            for (key, entry) in data_from_json(&args["data"]) {
+                match self.find_resp_in_table(key) {
+                    Some(n) if n.get_id() == self.addr.get_id() => {
+                        self.merge_entry(key, entry);
+                        mine.push(key);
+                    }
+                    Some(n) => {
+                        others
+                            .entry(n.get_id())
+                            .or_insert_with(|| (n, HashMap::new()))
+                            .1
+                            .insert(key, entry);
+                    }
+                    None => {}
+                }
+            }
+            println!("HINT : {:?} replayed the writes of {:?}", addr, mine);
+            self.replicate(&mine, None);
+            if others.is_empty() {
+                return;
+            }
+            match route.next() {
+                Some(next) => {
+                    for (n, entries) in others.into_values() {
+                        let hinted: Message =
+                            Hinted(addr.clone(), data_to_json(&entries), next.clone());
+                        self.forward(&n, hinted);
+                    }
+                }
+                None => println!("HINT : dropped after {} hops", route.get_hops()),
+            }
+        }
+    }
+
     fn handle_get(&mut self, args: Value) {
         if let Some(addr) = get_addr_from_json(&args, "address") {
             self.get += 1;
             // get request's key
             if let Some(key) = args["key"].as_i64() {
+                let consistency: Consistency = Consistency::from_json(&args["consistency"]);
+                let route: Route = Route::from_json(&args["route"]).through(self.addr.get_id());
+                if consistency != Consistency::One {
+                    // only the owner knows where the other copies are
+                    if let Some(next_addr) = self.find_resp_in_table(key) {
+                        if self.addr.get_id() == next_addr.get_id() {
+                            self.read(addr, key, consistency, route);
+                        } else {
+                            self.pass_get(addr, key, consistency, route, next_addr);
+                        }
+                    }
+                    return;
+                }
                 // try to see if the node already has the key
-                if let Some(v) = self.data.get(&key) {
+                if let Some(e) = self.lookup(key) {
                     // yes
                     if self.addr == addr {
                         // if i'm the one who ask the key then i print it
-                        println!("{}", v);
+                        println!("{} (version {:?})", e.get_value(), e.get_version());
                     } else {
                         // else i send the response to the node who requested it
-                        addr.send_message(Answer(key, *v, true));
+                        addr.send_message(Answer(
+                            key,
+                            e.get_value(),
+                            true,
+                            e.get_version(),
+                            Some(route),
+                        ));
                     }
                 } else {
                     // if i do not own the key
@@ -190,10 +802,16 @@ impl Node {
                     if let Some(next_addr) = self.find_resp_in_table(key) {
                         if self.addr.get_id() == next_addr.get_id() {
                             // if i'm the one who normally has it then send an error
-                            addr.send_message(Answer(key, 0.0, false));
+                            addr.send_message(Answer(
+                                key,
+                                0.0,
+                                false,
+                                Version::new(0, self.addr.get_id()),
+                                Some(route),
+                            ));
                         } else {
                             // else send the request to the next node
-                            next_addr.send_message(Get(addr, key));
+                            self.pass_get(addr, key, consistency, route, next_addr);
                         }
                     }
                 }
@@ -201,20 +819,229 @@ impl Node {
         }
     }
 
-    fn handle_get_resp(&self, args: Value) {
+    /// Passes a `Get` for a key I do not own on to `next_addr`, or gives it back to the
+    /// requester when I redirect.
+    fn pass_get(
+        &mut self,
+        requester: Address,
+        key: i64,
+        consistency: Consistency,
+        route: Route,
+        next_addr: Address,
+    ) {
+        if let Some(next) = self.next_route(&route, "GET", &requester, key) {
+            let (next_addr, next): (Address, Route) = self.via_cache(key, next_addr, next);
+            if self.redirect {
+                let retry: Route = next.cached_by(requester.clone());
+                let get: Message = Get(requester.clone(), key, consistency, retry);
+                self.redirect_to(&requester, key, &next_addr, get);
+            } else {
+                self.report_misdirected(&route, key, &next_addr);
+                next_addr.send_message(Get(requester, key, consistency, next));
+            }
+        }
+    }
+
+    fn handle_scan(&mut self, args: Value) {
+        if let Some(addr) = get_addr_from_json(&args, "address") {
+            self.get += 1;
+            if let (Some(start), Some(end)) = (args["start"].as_i64(), args["end"].as_i64()) {
+                let origin: Option<i64> = args["origin"].as_i64();
+                let route: Route = Route::from_json(&args["route"]).through(self.addr.get_id());
+                if origin.is_none() {
+                    // the scan has not reached the owner of the start key yet
+                    if let Some(next_addr) = self.find_resp_in_table(start) {
+                        if self.addr.get_id() != next_addr.get_id() {
+                            if let Some(next) = self.next_route(&route, "SCAN", &addr, start) {
+                                next_addr.send_message(Scan(addr, start, end, None, next));
+                            }
+                            return;
+                        }
+                    } else {
+                        return;
+                    }
+                }
+                let origin: i64 = origin.unwrap_or_else(|| self.addr.get_id());
+                let successor: Address = self.get_successor();
+                let last: bool =
+                    successor.get_id() == origin || self.scan_ends_here(start, end, origin);
+
+                let now: i64 = now_millis();
+                let found: Vec<(i64, Entry)> = self
+                    .data
+                    .range(start, end)
+                    .into_iter()
+                    .filter(|(k, e)| self.is_mine(key_position(*k)) && !e.is_expired(now))
+                    .collect();
+                self.send_pages(&addr, &found, last, ScanPage);
+                if !last {
+                    if let Some(next) = self.next_route(&route, "SCAN", &addr, start) {
+                        successor.send_message(Scan(addr, start, end, Some(origin), next));
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_dump(&mut self, args: Value) {
+        self.mgt += 1;
+        if let Some(addr) = get_addr_from_json(&args, "address") {
+            let origin: i64 = args["origin"]
+                .as_i64()
+                .unwrap_or_else(|| self.addr.get_id());
+            let route: Route = Route::from_json(&args["route"]).through(self.addr.get_id());
+            let successor: Address = self.get_successor();
+            let last: bool = successor.get_id() == origin;
+            let now: i64 = now_millis();
+            let found: Vec<(i64, Entry)> = self
+                .data
+                .iter()
+                .filter(|(k, e)| self.is_mine(key_position(*k)) && !e.is_expired(now))
+                .collect();
+            self.send_pages(&addr, &found, last, DumpPage);
+            if !last {
+                // the origin may have moved meanwhile, the walk must still end
+                if let Some(next) = self.next_route(&route, "DUMP", &addr, origin) {
+                    successor.send_message(Dump(addr, Some(origin), next));
+                }
+            }
+        }
+    }
+
+    fn handle_multi_put(&mut self, args: Value) {
+        self.put += 1;
+        if let Some(addr) = get_addr_from_json(&args, "address") {
+            if let (Some(id), Some(entries)) = (args["id"].as_i64(), args["entries"].as_array()) {
+                let route: Route = Route::from_json(&args["route"]).through(self.addr.get_id());
+                let (mine, others) = self.split_batch(entries, |e| e["key"].as_i64());
+                if !others.is_empty() {
+                    if let Some(next) = self.next_route(&route, "MULTI PUT", &addr, id) {
+                        for (next_addr, group) in others {
+                            let writes: Vec<(i64, f64, Option<i64>)> = group
+                                .iter()
+                                .filter_map(|e| {
+                                    Some((
+                                        e["key"].as_i64()?,
+                                        e["value"].as_f64()?,
+                                        e["ttl"].as_i64(),
+                                    ))
+                                })
+                                .collect();
+                            let batch: Message =
+                                MultiPut(addr.clone(), Value::Array(group), id, next.clone());
+                            if let Some(kept) = self.forward_writes(&next_addr, batch, &writes) {
+                                addr.send_message(MultiAnswer(id, hinted_results(&writes, &kept)));
+                            }
+                        }
+                    }
+                }
+                if !mine.is_empty() {
+                    println!("MULTI PUT : I'm updating my data");
+                    let mut results: Map<String, Value> = Map::new();
+                    let mut keys: Vec<i64> = Vec::new();
+                    for e in mine {
+                        if let (Some(key), Some(v)) = (e["key"].as_i64(), e["value"].as_f64()) {
+                            let ttl: Option<i64> = e["ttl"].as_i64();
+                            if ttl.is_some_and(|t| t <= 0) {
+                                results.insert(key.to_string(), json!({ "error": TTL_REFUSED }));
+                                continue;
+                            }
+                            let version: Version = self.store(key, v, ttl);
+                            results.insert(key.to_string(), json!({"version" : version.to_json()}));
+                            keys.push(key);
+                        }
+                    }
+                    self.replicate(&keys, None);
+                    addr.send_message(MultiAnswer(id, Value::Object(results)));
+                }
+            }
+        }
+    }
+
+    fn handle_multi_get(&mut self, args: Value) {
+        self.get += 1;
+        if let Some(addr) = get_addr_from_json(&args, "address") {
+            if let (Some(id), Some(keys)) = (args["id"].as_i64(), args["keys"].as_array()) {
+                let route: Route = Route::from_json(&args["route"]).through(self.addr.get_id());
+                let (mine, others) = self.split_batch(keys, |k| k.as_i64());
+                if !others.is_empty() {
+                    if let Some(next) = self.next_route(&route, "MULTI GET", &addr, id) {
+                        for (next_addr, group) in others {
+                            next_addr.send_message(MultiGet(
+                                addr.clone(),
+                                Value::Array(group),
+                                id,
+                                next.clone(),
+                            ));
+                        }
+                    }
+                }
+                if !mine.is_empty() {
+                    let mut results: Map<String, Value> = Map::new();
+                    for key in mine.iter().filter_map(|k| k.as_i64()) {
+                        let result: Value = match self.lookup(key) {
+                            Some(e) => {
+                                json!({"value" : e.get_value(), "value_exists" : true, "version" : e.get_version().to_json()})
+                            }
+                            None => json!({"value_exists" : false}),
+                        };
+                        results.insert(key.to_string(), result);
+                    }
+                    addr.send_message(MultiAnswer(id, Value::Object(results)));
+                }
+            }
+        }
+    }
+
+    fn handle_get_resp(&mut self, args: Value) {
         if let Some(addr) = get_addr_from_json(&args, "address") {
             if let Some(key) = args["key"].as_i64() {
+                let route: Route = Route::from_json(&args["route"]).through(self.addr.get_id());
                 if let Some(next_addr) = self.find_resp_in_table(key) {
                     if self.addr.get_id() == next_addr.get_id() {
-                        addr.send_message(AnswerResp(key, self.addr.clone()));
-                    } else {
-                        next_addr.send_message(GetResp(addr, key));
+                        addr.send_message(AnswerResp(
+                            key,
+                            self.addr.clone(),
+                            self.previous.get_id(),
+                            route,
+                        ));
+                    } else if let Some(next) = self.next_route(&route, "GET_RESP", &addr, key) {
+                        self.report_misdirected(&route, key, &next_addr);
+                        let (next_addr, next): (Address, Route) =
+                            self.via_cache(key, next_addr, next);
+                        next_addr.send_message(GetResp(addr, key, next));
                     }
                 }
             }
         }
     }
 
+    /// One step of an iterative lookup: tells the requester who owns the key if I know it,
+    /// else which of my fingers to ask next.
+    fn handle_find_next(&self, args: Value) {
+        if let (Some(addr), Some(key)) =
+            (get_addr_from_json(&args, "address"), args["key"].as_i64())
+        {
+            let position: RingId = RingId::new(key);
+            let successor: Address = self.get_successor();
+            let owner: Option<Address> = if self.is_mine(position.get()) {
+                Some(self.addr.clone())
+            } else if Interval::open_closed(self.addr.get_ring_id(), successor.get_ring_id())
+                .contains(position)
+            {
+                Some(successor)
+            } else {
+                None
+            };
+            let candidates: Vec<Value> = self
+                .closest_preceding(position.get())
+                .iter()
+                .map(|a| json!({"address" : a.to_json()}))
+                .collect();
+            addr.send_message(NextHop(self.addr.clone(), key, owner, json!(candidates)));
+        }
+    }
+
     fn handle_get_stat(&self, args: Value) {
         if let Some(addr) = get_addr_from_json(&args, "address") {
             match (
@@ -252,148 +1079,1354 @@ impl Node {
         }
     }
 
+    fn handle_tick(&mut self, _args: Value) {
+        let now: i64 = now_millis();
+        let expired: Vec<i64> = self
+            .data
+            .iter()
+            .filter(|(_, e)| e.is_expired(now))
+            .map(|(k, _)| k)
+            .collect();
+        for key in expired {
+            self.data.delete(key);
+        }
+        self.replica_data.retain(|_, e| !e.is_expired(now));
+        self.expire_requests(now);
+        self.data.compact();
+        self.replay_hints();
+        self.start_anti_entropy();
+        if self.proximity && self.moving.is_none() {
+            self.select_fingers();
+        }
+        if let Some(j) = self.joining.as_mut() {
+            // my Hello, the HelloOK or the commit got lost, or the handoff was given up
+            j.ticks += 1;
+            if j.ticks >= JOIN_RETRY_TICKS {
+                j.ticks = 0;
+                self.start_join();
+            }
+        }
+        if let Some(h) = self.handoff.as_mut() {
+            // the last chunk, its ack, the HelloOK or the confirmation got lost
+            h.retries += 1;
+            if h.retries > TRANSFER_RETRIES {
+                println!(
+                    "HANDOFF : {:?} does not answer, I keep its keys",
+                    h.receiver
+                );
+                self.handoff = None;
+                self.next_waiting_hello();
+            } else {
+                self.send_next_chunk();
+            }
+        }
+    }
+
+    fn handle_replicate(&mut self, args: Value) {
+        if let (Some(owner), Some(hops)) =
+            (get_addr_from_json(&args, "address"), args["hops"].as_i64())
+        {
+            for (key, entry) in data_from_json(&args["data"]) {
+                self.clock = self.clock.max(entry.get_version().get_counter());
+                let is_newer: bool = match self.replica_data.get(&key) {
+                    Some(current) => entry.is_newer_than(current),
+                    None => true,
+                };
+                if is_newer {
+                    self.replica_data.insert(key, entry);
+                }
+            }
+            let request: Option<i64> = args["request"].as_i64();
+            if let Some(request) = request {
+                owner.send_message(ReplicaAck(self.addr.clone(), request));
+            }
+            let successor: Address = self.get_successor();
+            if hops > 1 && successor != owner && successor != self.addr {
+                successor.send_message(Replicate(
+                    owner,
+                    args["data"].to_owned(),
+                    hops - 1,
+                    request,
+                ));
+            }
+        }
+    }
+
+    fn handle_replica_ack(&mut self, args: Value) {
+        if let (Some(replica), Some(request)) = (
+            get_addr_from_json(&args, "address"),
+            args["request"].as_i64(),
+        ) {
+            if let Some(p) = self.requests.get_mut(&request) {
+                p.answers.push((replica, None));
+            }
+            self.settle(request);
+        }
+    }
+
+    /// Replica side of a quorum read: sends my copy of the key to the owner and passes the
+    /// read on to the next replica.
+    fn handle_replica_read(&mut self, args: Value) {
+        if let (Some(owner), Some(request), Some(key), Some(hops)) = (
+            get_addr_from_json(&args, "address"),
+            args["request"].as_i64(),
+            args["key"].as_i64(),
+            args["hops"].as_i64(),
+        ) {
+            let now: i64 = now_millis();
+            let entry: Value = match self.replica_data.get(&key) {
+                Some(e) if !e.is_expired(now) => e.to_json(key),
+                _ => Value::Null,
+            };
+            owner.send_message(ReplicaValue(self.addr.clone(), request, entry));
+            let successor: Address = self.get_successor();
+            if hops > 1 && successor != owner && successor != self.addr {
+                successor.send_message(ReplicaRead(owner, request, key, hops - 1));
+            }
+        }
+    }
+
+    fn handle_replica_value(&mut self, args: Value) {
+        if let (Some(replica), Some(request)) = (
+            get_addr_from_json(&args, "address"),
+            args["request"].as_i64(),
+        ) {
+            if let Some(p) = self.requests.get_mut(&request) {
+                let entry: Option<Entry> = Entry::from_json(&args["entry"]).map(|(_, e)| e);
+                p.answers.push((replica, entry));
+            }
+            self.settle(request);
+        }
+    }
+
+    /// Remembers a node met on the way, its round trip time is measured at the next tick.
+    fn learn(&mut self, addr: &Address) {
+        if self.proximity && *addr != self.addr {
+            self.neighbours
+                .entry(addr.get_id())
+                .or_insert_with(|| Neighbour {
+                    addr: addr.clone(),
+                    rtt: None,
+                    measured: 0,
+                });
+        }
+    }
+
+    /// Proximity neighbour selection: the finger of the key `k` may be any node of the slot
+    /// from `k` up to the next finger key without breaking the routing, so it becomes the one
+    /// I reach the fastest. The first finger stays my successor. A random key of every slot is
+    /// then looked up to discover more of its nodes. The neighbours whose round trip time is
+    /// missing or too old are measured by another thread, a few at a time, which sends me the
+    /// results in a `Probed`.
+    fn select_fingers(&mut self) {
+        let fingers: Vec<Address> = self.association.values().cloned().collect();
+        for a in fingers
+            .iter()
+            .chain(std::iter::once(&self.previous.clone()))
+        {
+            self.learn(a);
+        }
+        let now: i64 = now_millis();
+        let mut stale: Vec<&mut Neighbour> = self
+            .neighbours
+            .values_mut()
+            .filter(|n| now - n.measured >= RTT_MAX_AGE)
+            .collect();
+        stale.sort_by_key(|n| n.measured);
+        let probed: Vec<Address> = stale
+            .into_iter()
+            .take(PROBES_PER_TICK)
+            .map(|n| {
+                // not picked again while it is being measured
+                n.measured = now;
+                n.addr.clone()
+            })
+            .collect();
+        if !probed.is_empty() {
+            let me: Address = self.addr.clone();
+            std::thread::spawn(move || {
+                let results: Vec<Value> = probed
+                    .iter()
+                    .map(|a| {
+                        let rtt: Option<i64> = a.probe(PROBE_TIMEOUT).map(|d| d.as_micros() as i64);
+                        json!({"address" : a.to_json(), "rtt" : rtt})
+                    })
+                    .collect();
+                me.send_message(Probed(json!(results)));
+            });
+        }
+        self.pick_fingers();
+        let me: RingId = self.addr.get_ring_id();
+        let mut keys: Vec<RingId> = self.association.keys().map(|&k| RingId::new(k)).collect();
+        keys.sort_by_key(|&k| me.distance(k));
+        let mut rng = rand::thread_rng();
+        for (i, &key) in keys.iter().enumerate().skip(1) {
+            let end: RingId = keys.get(i + 1).copied().unwrap_or(me);
+            let slot: Interval = Interval::closed_open(key, end);
+            let probe: RingId = key + rng.gen_range(0..slot.width());
+            self.get_successor().send_message(GetResp(
+                self.addr.clone(),
+                probe.get(),
+                Route::new(false),
+            ));
+        }
+    }
+
+    /// Points every finger but the first to the node of its slot with the lowest known round
+    /// trip time.
+    fn pick_fingers(&mut self) {
+        let me: RingId = self.addr.get_ring_id();
+        let mut keys: Vec<RingId> = self.association.keys().map(|&k| RingId::new(k)).collect();
+        keys.sort_by_key(|&k| me.distance(k));
+        for (i, &key) in keys.iter().enumerate().skip(1) {
+            let end: RingId = keys.get(i + 1).copied().unwrap_or(me);
+            let slot: Interval = Interval::closed_open(key, end);
+            let closest: Option<&Neighbour> = self
+                .neighbours
+                .values()
+                .filter(|n| n.rtt.is_some() && slot.contains(n.addr.get_ring_id()))
+                .min_by_key(|n| n.rtt);
+            if let Some(n) = closest {
+                self.association.insert(key.get(), n.addr.clone());
+            }
+        }
+    }
+
+    /// Round trip times measured by the probing thread: the neighbours which did not answer
+    /// are forgotten.
+    fn handle_probed(&mut self, args: Value) {
+        if let Some(results) = args["results"].as_array() {
+            let now: i64 = now_millis();
+            for r in results {
+                let id: i64 = match get_addr_from_json(r, "address") {
+                    Some(a) => a.get_id(),
+                    None => continue,
+                };
+                match (r["rtt"].as_i64(), self.neighbours.get_mut(&id)) {
+                    (Some(sample), Some(n)) => {
+                        // smoothed like the round trip time of TCP
+                        n.rtt = Some(n.rtt.map_or(sample, |rtt| (7 * rtt + sample) / 8));
+                        n.measured = now;
+                    }
+                    (None, Some(n)) => {
+                        println!("PROXIMITY : {:?} does not answer", n.addr);
+                        self.neighbours.remove(&id);
+                    }
+                    _ => {}
+                }
+            }
+            if self.proximity && self.moving.is_none() {
+                self.pick_fingers();
+            }
+        }
+    }
+
+    /// Sends the Merkle tree of my range to my replicas, each one answers with the positions
+    /// where its copy differs.
+    fn start_anti_entropy(&self) {
+        let successor: Address = self.get_successor();
+        if self.replicas > 0 && successor != self.addr && self.moving.is_none() {
+            let tree: MerkleTree = MerkleTree::build(
+                self.data
+                    .iter()
+                    .filter(|(k, _)| self.is_mine(key_position(*k))),
+            );
+            successor.send_message(SyncTree(
+                self.addr.clone(),
+                self.previous.get_id(),
+                tree.to_json(),
+                self.replicas,
+            ));
+        }
+    }
+
+    fn handle_sync_tree(&mut self, args: Value) {
+        self.mgt += 1;
+        if let (Some(owner), Some(lower), Some(tree), Some(hops)) = (
+            get_addr_from_json(&args, "address"),
+            args["lower"].as_i64(),
+            MerkleTree::from_json(&args["tree"]),
+            args["hops"].as_i64(),
+        ) {
+            let copy: Vec<(i64, Entry)> = self.replica_copy(lower, owner.get_id());
+            let positions: Vec<i64> = tree.diff(&MerkleTree::build(copy.iter().cloned()));
+            if !positions.is_empty() {
+                let entries: HashMap<i64, Entry> = copy
+                    .into_iter()
+                    .filter(|(k, _)| positions.contains(&key_position(*k)))
+                    .collect();
+                owner.send_message(SyncDiff(
+                    self.addr.clone(),
+                    json!(positions),
+                    data_to_json(&entries),
+                ));
+            }
+            let successor: Address = self.get_successor();
+            if hops > 1 && successor != owner && successor != self.addr {
+                successor.send_message(SyncTree(owner, lower, args["tree"].to_owned(), hops - 1));
+            }
+        }
+    }
+
+    /// Owner side of a repair: keeps the replica's entries that are newer than mine, then sends
+    /// back my entries for the differing positions.
+    fn handle_sync_diff(&mut self, args: Value) {
+        if let (Some(replica), Some(positions)) = (
+            get_addr_from_json(&args, "address"),
+            args["positions"].as_array(),
+        ) {
+            let positions: Vec<i64> = positions.iter().filter_map(|p| p.as_i64()).collect();
+            for (key, entry) in data_from_json(&args["data"]) {
+                if self.is_mine(key_position(key)) {
+                    self.merge_entry(key, entry);
+                }
+            }
+            let entries: HashMap<i64, Entry> = self
+                .data
+                .iter()
+                .filter(|(k, _)| positions.contains(&key_position(*k)))
+                .collect();
+            replica.send_message(SyncRepair(
+                self.addr.clone(),
+                json!(positions),
+                data_to_json(&entries),
+            ));
+        }
+    }
+
+    /// Replica side of a repair: my copy of the differing positions becomes the owner's.
+    fn handle_sync_repair(&mut self, args: Value) {
+        if let Some(positions) = args["positions"].as_array() {
+            let positions: Vec<i64> = positions.iter().filter_map(|p| p.as_i64()).collect();
+            self.replica_data
+                .retain(|k, _| !positions.contains(&key_position(*k)));
+            for (key, entry) in data_from_json(&args["data"]) {
+                self.clock = self.clock.max(entry.get_version().get_counter());
+                self.replica_data.insert(key, entry);
+            }
+        }
+    }
+
+    /// Adds my load to the reports of a rebalance walk. The last node of the walk moves the
+    /// least loaded node next to the most loaded one when the gap is large enough.
+    fn handle_rebalance(&mut self, args: Value) {
+        self.mgt += 1;
+        if let (Some(addr), Some(metric), Some(reports)) = (
+            get_addr_from_json(&args, "address"),
+            args["metric"].as_str(),
+            args["reports"].as_array(),
+        ) {
+            let mut reports: Vec<Value> = reports.clone();
+            reports.push(self.load_report(metric));
+            let origin: Option<Address> = get_addr_from_json(&reports[0], "address");
+            let route: Route = Route::from_json(&args["route"]).through(self.addr.get_id());
+            let successor: Address = self.get_successor();
+            if successor != self.addr && Some(&successor) != origin.as_ref() {
+                let id: i64 = origin.map_or(self.addr.get_id(), |o| o.get_id());
+                if let Some(next) = self.next_route(&route, "REBALANCE", &addr, id) {
+                    successor.send_message(Rebalance(
+                        addr,
+                        metric.to_string(),
+                        json!(reports),
+                        next,
+                    ));
+                }
+                return;
+            }
+            let moved: Value = match plan_move(&reports) {
+                Some((cold, hot, id)) => {
+                    println!("REBALANCE : {:?} moves to {} next to {:?}", cold, id, hot);
+                    let moved: Value =
+                        json!({"node" : cold.to_json(), "next_to" : hot.to_json(), "id" : id});
+                    cold.send_message(Move(hot, id));
+                    moved
+                }
+                None => Value::Null,
+            };
+            addr.send_message(Rebalanced(json!(reports), moved));
+        }
+    }
+
+    /// Leaves the ring, my keys going to my successor, and joins it again at `id` in the
+    /// interval of the `hot` node, which hands me the keys below `id`. The keys are streamed
+    /// to my successor like to a joiner, and stay mine until it acknowledged all of them.
+    fn handle_move(&mut self, args: Value) {
+        if let (Some(hot), Some(id)) = (get_addr_from_json(&args, "address"), args["id"].as_i64()) {
+            let successor: Address = self.get_successor();
+            if successor == self.addr || self.moving.is_some() || self.handoff.is_some() {
+                println!("MOVE : I can't move now");
+                return;
+            }
+            println!(
+                "MOVE : I hand my keys to {:?} before moving to {}",
+                successor, id
+            );
+            let mut keys: Vec<i64> = self
+                .data
+                .keys_in_range(self.previous.get_id(), self.addr.get_id());
+            keys.sort_unstable();
+            self.handoff = Some(Handoff {
+                receiver: successor,
+                keys,
+                sent: HashMap::new(),
+                next: 0,
+                opened: false,
+                retries: 0,
+                route: Route::new(false),
+                move_to: Some((hot, key_position(id))),
+            });
+            self.send_next_chunk();
+        }
+    }
+
+    /// Every chunk of my move was acknowledged: the entries written since their chunk was sent
+    /// go in one more round, else my successor has them all and I leave.
+    fn finish_move(&mut self) {
+        let data: &S = &self.data;
+        let h: &mut Handoff = match self.handoff.as_mut() {
+            Some(h) => h,
+            None => return,
+        };
+        let written: Vec<i64> = data
+            .keys_in_range(self.previous.get_id(), self.addr.get_id())
+            .into_iter()
+            .filter(|&k| {
+                data.get(k)
+                    .is_some_and(|e| h.sent.get(&k) != Some(&e.get_version()))
+            })
+            .collect();
+        if !written.is_empty() {
+            h.keys.extend(written);
+            self.send_next_chunk();
+            return;
+        }
+        let (successor, hot, id): (Address, Address, i64) = match self.handoff.take() {
+            Some(Handoff {
+                receiver,
+                move_to: Some((hot, id)),
+                ..
+            }) => (receiver, hot, id),
+            _ => return,
+        };
+        if successor
+            .send_message(Leave(self.addr.clone(), self.previous.clone()))
+            .is_none()
+        {
+            println!("MOVE : {:?} does not answer, I stay", successor);
+            self.next_waiting_hello();
+            return;
+        }
+        if !self.shares_storage(&successor) {
+            self.data
+                .extract_range(self.previous.get_id(), self.addr.get_id());
+        }
+        self.addr = Address::new(self.addr.get_ip(), self.addr.get_port(), id);
+        self.previous = self.addr.clone();
+        self.association = fingers(id, &successor);
+        self.replica_data.clear();
+        self.moving = Some(successor);
+        self.join(hot);
+        self.start_join();
+    }
+
+    /// A request I sent from my location cache reached a node which does not own its key.
+    /// The node it points me to is cached instead when it knows the range that node owns.
+    fn handle_moved(&mut self, args: Value) {
+        if let Some(key) = args["key"].as_i64() {
+            self.cache.invalidate(key);
+            if let (Some(next), Some(previous)) =
+                (get_addr_from_json(&args, "next"), args["previous"].as_i64())
+            {
+                self.cache.insert(previous, next);
+            }
+        }
+    }
+
+    /// My previous node leaves the ring: its keys, already transferred, become mine and every
+    /// finger pointing to it must now point to me.
+    fn handle_leave(&mut self, args: Value) {
+        self.mgt += 1;
+        if let (Some(leaving), Some(previous)) = (
+            get_addr_from_json(&args, "address"),
+            get_addr_from_json(&args, "previous"),
+        ) {
+            let keys: Vec<i64> = self.data.keys_in_range(previous.get_id(), leaving.get_id());
+            if self.previous == leaving {
+                self.previous = previous;
+            }
+            self.replicate(&keys, None);
+            self.handle_replace(json!({
+                "old" : leaving.to_json(),
+                "new" : self.addr.to_json(),
+                "origin" : self.addr.get_id(),
+            }));
+        }
+    }
+
+    /// Walks the ring once from `origin`, replacing `old` by `new` in every finger table.
+    fn handle_replace(&mut self, args: Value) {
+        if let (Some(old), Some(new), Some(origin)) = (
+            get_addr_from_json(&args, "old"),
+            get_addr_from_json(&args, "new"),
+            args["origin"].as_i64(),
+        ) {
+            for addr in self.association.values_mut() {
+                if *addr == old {
+                    *addr = new.clone();
+                }
+            }
+            let successor: Address = self.get_successor();
+            if successor != self.addr && successor.get_id() != origin {
+                successor.send_message(Replace(old, new, origin));
+            }
+        }
+    }
+
+    /// My load for a rebalance walk, measured in keys, bytes or requests since the last walk.
+    fn load_report(&mut self, metric: &str) -> Value {
+        let mine: Vec<(i64, Entry)> = self
+            .data
+            .iter()
+            .filter(|(k, _)| self.is_mine(key_position(*k)))
+            .collect();
+        let keys: i64 = mine.len() as i64;
+        let bytes: i64 = mine
+            .iter()
+            .map(|(k, e)| e.to_json(*k).to_string().len() as i64)
+            .sum();
+        // a node joining at the split takes the lower half of my entries, by count or by size
+        let weights: Vec<(i64, i64)> = mine
+            .iter()
+            .map(|(k, e)| match metric {
+                "bytes" => (*k, e.to_json(*k).to_string().len() as i64),
+                _ => (*k, 1),
+            })
+            .collect();
+        let requests: i64 = self.get + self.put - self.reported_requests;
+        self.reported_requests = self.get + self.put;
+        let load: i64 = match metric {
+            "bytes" => bytes,
+            "requests" => requests,
+            _ => keys,
+        };
+        json!({
+            "address" : self.addr.to_json(),
+            "previous" : self.previous.get_id(),
+            "keys" : keys,
+            "bytes" : bytes,
+            "requests" : requests,
+            "load" : load,
+            "split" : split_position(self.previous.get_id(), self.addr.get_id(), &weights),
+        })
+    }
+
     fn handle_hello(&mut self, args: Value) {
         if let Some(addr) = get_addr_from_json(&args, "address") {
-            if let Some(resp) = self.find_resp_in_table(addr.get_id()) {
+            let received: Route = Route::from_json(&args["route"]);
+            let route: Route = received.clone().through(self.addr.get_id());
+            if addr == self.previous && addr != self.addr {
+                // my previous node restarted with its saved state and still owns its keys,
+                // there is nothing to hand over, even while I rejoin myself
+                addr.send_message(HelloOK(
+                    addr.get_id(),
+                    self.addr.clone(),
+                    Value::Array(vec![]),
+                    addr.clone(),
+                    route,
+                ));
+            } else if self.joining.is_some() {
+                // my own fingers can't be trusted before my join is committed
+                self.keep_waiting(addr, received);
+            } else if let Some(resp) = self.find_resp_in_table(addr.get_id()) {
                 println!("{:?}", resp);
                 if resp.get_id() != self.addr.get_id() {
-                    resp.send_message(Hello(addr));
+                    if let Some(next) = self.next_route(&route, "HELLO", &addr, addr.get_id()) {
+                        resp.send_message(Hello(addr, next));
+                    }
                 } else if self.addr.get_id() == addr.get_id() {
                     addr.send_message(HelloKO(addr.get_id()));
+                } else if let Some(h) = self.handoff.as_ref() {
+                    if h.receiver == addr {
+                        // the joiner asks again, resume where the transfer stopped
+                        self.send_next_chunk();
+                    } else {
+                        self.keep_waiting(addr, received);
+                    }
                 } else {
-                    let node_data: HashMap<i64, f64> = self
+                    let mut keys: Vec<i64> = self
                         .data
-                        .clone()
-                        .into_iter()
-                        .filter(|c| {
-                            c.0 <= addr.get_id() || self.previous.get_id() > self.addr.get_id()
-                        })
-                        .collect();
-
-                    for x in node_data.keys() {
-                        self.data.remove(x);
-                    }
+                        .keys_in_range(self.previous.get_id(), addr.get_id());
+                    keys.sort_unstable();
+                    self.handoff = Some(Handoff {
+                        receiver: addr,
+                        keys,
+                        sent: HashMap::new(),
+                        next: 0,
+                        opened: false,
+                        retries: 0,
+                        route,
+                        move_to: None,
+                    });
+                    self.send_next_chunk();
+                }
+            }
+        }
+    }
 
-                    let old_previous: Address = self.previous.clone();
+    /// Merges the entries `from` handed over to me, keeping their versions while I am joining
+    /// unless they are in my own storage already.
+    fn merge_received(&mut self, from: &Address, data: HashMap<i64, Entry>) {
+        let shared: bool = self.shares_storage(from);
+        for (key, entry) in data {
+            if let Some(j) = self.joining.as_mut().filter(|_| !shared) {
+                j.received.insert(key, entry.get_version());
+            }
+            self.merge_entry(key, entry);
+        }
+    }
+
+    /// Whether `other` is a virtual node of my own server, which keeps its entries in my
+    /// storage: what I hand over to it must stay there.
+    fn shares_storage(&self, other: &Address) -> bool {
+        other.get_ip() == self.addr.get_ip() && other.get_port() == self.addr.get_port()
+    }
 
-                    self.previous = addr.clone();
+    /// Drops the entries an earlier handoff gave me and nothing wrote since: it was given up
+    /// before its commit, and the new one sends the range again as it is now.
+    fn drop_received(&mut self) {
+        let received: HashMap<i64, Version> = match self.joining.as_mut() {
+            Some(j) => std::mem::take(&mut j.received),
+            None => return,
+        };
+        for (key, version) in received {
+            if self
+                .data
+                .get(key)
+                .is_some_and(|e| e.get_version() == version)
+            {
+                self.data.delete(key);
+            }
+        }
+    }
 
-                    let data: Value = json!(node_data);
+    fn handle_transfer(&mut self, args: Value) {
+        self.mgt += 1;
+        if let (Some(addr), Some(seq)) =
+            (get_addr_from_json(&args, "address"), args["seq"].as_i64())
+        {
+            if seq == 0 {
+                self.drop_received();
+            }
+            self.merge_received(&addr, data_from_json(&args["data"]));
+            addr.send_message(TransferAck(self.addr.clone(), seq));
+        }
+    }
 
-                    addr.send_message(HelloOK(
-                        addr.get_id(),
+    fn handle_transfer_ack(&mut self, args: Value) {
+        if let (Some(addr), Some(seq)) =
+            (get_addr_from_json(&args, "address"), args["seq"].as_i64())
+        {
+            if let Some(h) = self.handoff.as_mut() {
+                if h.receiver == addr && seq == h.next as i64 {
+                    h.next = (h.next + TRANSFER_CHUNK_SIZE).min(h.keys.len());
+                    h.opened = true;
+                    h.retries = 0;
+                    self.send_next_chunk();
+                }
+            }
+        }
+    }
+
+    /// Sends the chunk of the handoff starting at `next`, or asks the joiner to confirm once
+    /// every chunk has been acknowledged. A move is finished instead.
+    fn send_next_chunk(&mut self) {
+        let h: &mut Handoff = match self.handoff.as_mut() {
+            Some(h) => h,
+            None => return,
+        };
+        if h.opened && h.next >= h.keys.len() {
+            if h.move_to.is_some() {
+                self.finish_move();
+            } else {
+                self.prepare_handoff();
+            }
+            return;
+        }
+        let end: usize = (h.next + TRANSFER_CHUNK_SIZE).min(h.keys.len());
+        let mut chunk: HashMap<i64, Entry> = HashMap::new();
+        for &key in &h.keys[h.next..end] {
+            if let Some(e) = self.data.get(key) {
+                h.sent.insert(key, e.get_version());
+                chunk.insert(key, e);
+            }
+        }
+        h.receiver.send_message(Transfer(
+            self.addr.clone(),
+            data_to_json(&chunk),
+            h.next as i64,
+        ));
+    }
+
+    /// Sends the `HelloOK` with the entries written since their chunk was sent. The range
+    /// stays mine until the joiner confirms.
+    fn prepare_handoff(&mut self) {
+        let data: &S = &self.data;
+        if let Some(h) = self.handoff.as_mut() {
+            let node_data: HashMap<i64, Entry> = data
+                .keys_in_range(self.previous.get_id(), h.receiver.get_id())
+                .into_iter()
+                .filter_map(|k| data.get(k).map(|e| (k, e)))
+                .filter(|(k, e)| h.sent.get(k) != Some(&e.get_version()))
+                .collect();
+            for (&k, e) in node_data.iter() {
+                h.sent.insert(k, e.get_version());
+            }
+            h.receiver.send_message(HelloOK(
+                h.receiver.get_id(),
+                self.addr.clone(),
+                data_to_json(&node_data),
+                self.previous.clone(),
+                h.route.clone(),
+            ));
+        }
+    }
+
+    /// Hands the range over once the joiner confirmed it got every chunk and the `HelloOK`:
+    /// the entries written since travel with the `JoinCommit`, and only then does the joiner
+    /// become my previous node.
+    fn handle_join_confirm(&mut self, args: Value) {
+        self.mgt += 1;
+        if let Some(joiner) = get_addr_from_json(&args, "address") {
+            let ready: bool = self
+                .handoff
+                .as_ref()
+                .is_some_and(|h| h.receiver == joiner && h.opened && h.next >= h.keys.len());
+            if ready {
+                if let Some(h) = self.handoff.take() {
+                    let node_data: HashMap<i64, Entry> = if self.shares_storage(&h.receiver) {
+                        // the entries already are where the joiner reads them
+                        HashMap::new()
+                    } else {
+                        self.data
+                            .extract_range(self.previous.get_id(), h.receiver.get_id())
+                            .into_iter()
+                            .filter(|(k, e)| h.sent.get(k) != Some(&e.get_version()))
+                            .collect()
+                    };
+
+                    let old_previous: Address = self.previous.clone();
+
+                    self.previous = h.receiver.clone();
+                    // my fingers which went past the joiner, back to me when I was alone, must
+                    // point to it before the next Hello is routed
+                    self.point_fingers_to(&h.receiver);
+
+                    h.receiver.send_message(JoinCommit(
                         self.addr.clone(),
-                        data,
-                        old_previous.clone(),
+                        old_previous,
+                        data_to_json(&node_data),
                     ));
+                    self.next_waiting_hello();
                 }
+            } else if joiner == self.previous {
+                // the commit got lost or the joiner rejoins, the range is already its own
+                joiner.send_message(JoinCommit(
+                    self.addr.clone(),
+                    joiner.clone(),
+                    Value::Array(vec![]),
+                ));
             }
         }
     }
 
+    /// Keeps the `Hello` of `addr` for later, once however many times it says it again.
+    fn keep_waiting(&mut self, addr: Address, route: Route) {
+        match self.waiting.iter_mut().find(|(a, _)| *a == addr) {
+            Some(w) => w.1 = route,
+            None => self.waiting.push((addr, route)),
+        }
+    }
+
+    /// Goes on with the nodes which said `Hello` while a range was reserved, until one of them
+    /// reserves a range again.
+    fn next_waiting_hello(&mut self) {
+        while !self.waiting.is_empty() && self.handoff.is_none() && self.joining.is_none() {
+            let (addr, route): (Address, Route) = self.waiting.remove(0);
+            self.handle_hello(json!({ "address": addr.to_json(), "route": route.to_json() }));
+        }
+    }
+
     fn handle_hello_ok(&mut self, args: Value) {
         if let Some(addr_previous) = get_addr_from_json(&args, "address_previous") {
             if let Some(addr_resp) = get_addr_from_json(&args, "address_resp") {
-                self.previous = addr_previous;
-                if let Some(data) = args["data"].as_str() {
-                    self.data = serde_json::from_str(data).unwrap();
+                let route: Route = Route::from_json(&args["route"]);
+                println!(
+                    "HELLO : answered after {} hops, through {:?}",
+                    route.get_hops(),
+                    route.get_trace()
+                );
+                self.merge_received(&addr_resp, data_from_json(&args["data"]));
+                // a node answering a rejoin sends back our own address: keep the saved previous
+                if addr_previous != self.addr {
+                    if let Some(j) = self.joining.as_mut() {
+                        j.previous = Some(addr_previous);
+                    }
                 }
+                addr_resp.send_message(JoinConfirm(self.addr.clone()));
+            }
+        }
+    }
+
+    fn handle_join_commit(&mut self, args: Value) {
+        self.mgt += 1;
+        if let (Some(addr_resp), Some(addr_previous)) = (
+            get_addr_from_json(&args, "address"),
+            get_addr_from_json(&args, "previous"),
+        ) {
+            for (key, entry) in data_from_json(&args["data"]) {
+                self.merge_entry(key, entry);
+            }
+            let j: Joining = match self.joining.take() {
+                Some(j) => j,
+                // a commit sent again, I already joined
+                None => return,
+            };
+            println!("HELLO : joined before {:?}", addr_resp);
+            self.moving = None;
+            if addr_previous != self.addr {
+                self.previous = addr_previous;
+            } else if let Some(previous) = j.previous {
+                self.previous = previous;
+            }
+            if self.association.values().all(|a| *a == self.addr) {
+                self.association = fingers(self.addr.get_id(), &addr_resp);
+            }
+            if self.previous != self.addr {
+                // the nodes whose farthest finger reaches past my previous node may point
+                // to me now
+                let me: RingId = self.addr.get_ring_id();
+                let reach: i64 =
+                    (HALF_CIRCLE - 1 + self.previous.get_ring_id().distance(me)).min(MAX_NODE - 1);
                 self.previous.send_message(UpdateTable(
                     self.addr.clone(),
-                    (self.addr.get_id() - HALF_CIRCLE) % MAX_NODE,
+                    (me - reach).get(),
                     HALF_CIRCLE,
                 ));
-                for (&a, _b) in self.association.iter() {
-                    addr_resp.send_message(GetResp(self.addr.clone(), a));
-                }
             }
+            for (&a, _b) in self.association.iter() {
+                addr_resp.send_message(GetResp(self.addr.clone(), a, Route::new(false)));
+            }
+            self.next_waiting_hello();
         }
     }
 
     fn handle_hello_ko(&mut self, _args: Value) {
+        self.joining = None;
         self.exit = true;
     }
 
     fn handle_update_table(&mut self, args: Value) {
         self.mgt += 1;
         if let Some(addr) = get_addr_from_json(&args, "address") {
-            match (args["id_lower_key"].as_i64(), args["amount"].as_i64()) {
-                (Some(id_lk), Some(amt)) => {
-                    if self.addr.get_id() >= id_lk {
-                        let id: i64 = addr.get_id();
-                        let my_id: i64 = self.addr.get_id();
-                        println!("{:?}", args);
-                        self.association = self
-                            .association
-                            .iter()
-                            .map(|(&pointed_key, pointed_addr)| {
-                                let id: i64 = if id == 0 { MAX_NODE } else { id };
-                                return if id >= pointed_key
-                                    && (pointed_addr.get_id() > id
-                                        || (pointed_addr.get_id() + MAX_NODE > id
-                                            && self.addr.get_id() > pointed_addr.get_id())
-                                        || pointed_addr.get_id() == my_id)
-                                {
-                                    (pointed_key, addr.clone())
-                                } else {
-                                    (pointed_key, pointed_addr.clone())
-                                };
-                            })
-                            .collect();
-
-                        if self.addr != addr {
-                            self.previous.send_message(UpdateTable(addr, id_lk, amt));
-                        }
+            if let (Some(id_lk), Some(amt)) =
+                (args["id_lower_key"].as_i64(), args["amount"].as_i64())
+            {
+                let joiner: RingId = addr.get_ring_id();
+                // the nodes from `id_lower_key` up to the joiner may have a finger on it
+                let concerned: Interval = Interval::closed_open(RingId::new(id_lk), joiner);
+                if concerned.contains(self.addr.get_ring_id()) {
+                    println!("{:?}", args);
+                    self.point_fingers_to(&addr);
+                    if self.previous != addr && self.previous != self.addr {
+                        self.previous.send_message(UpdateTable(addr, id_lk, amt));
                     }
                 }
-                _ => {}
             }
         }
     }
-    fn is_between(id: i64, lower: i64, upper: i64) -> bool {
-        return (lower == upper)
-            || (id <= upper && id > lower)
-            || (id > lower && upper >= 0 && lower > upper)
-            || (id >= 0 && lower > upper && id <= upper)
-            || (id == upper);
-    }
-    fn is_mine(&self, id: i64) -> bool {
-        let previous_id: i64 = self.previous.get_id();
-        let my_id: i64 = self.addr.get_id();
-        return Node::is_between(id, previous_id, my_id);
-    }
-    fn next_is_the_owner(&self, id: i64) -> Option<Address> {
-        let next_association: i64 = self.addr.get_id() + 1;
-        if let Some(a) = self.association.get(&next_association) {
-            if Node::is_between(id, self.addr.get_id(), a.get_id()) {
-                Some(a.clone())
-            } else {
-                None
+
+    /// Makes the fingers whose key `addr` is closer to than the node they point to point to it.
+    fn point_fingers_to(&mut self, addr: &Address) {
+        let joiner: RingId = addr.get_ring_id();
+        for (&pointed_key, pointed_addr) in self.association.iter_mut() {
+            let finger: RingId = RingId::new(pointed_key);
+            // a finger points to the first node at or after its key
+            if finger.distance(joiner) < finger.distance(pointed_addr.get_ring_id()) {
+                *pointed_addr = addr.clone();
             }
+        }
+    }
+
+    /// The route to pass a request on with, `None` when its hop budget is spent: it is most
+    /// likely looping because of a stale finger, so it is dropped and the requester gets an
+    /// `Error` for the request `id`.
+    fn next_route(&self, route: &Route, cmd: &str, requester: &Address, id: i64) -> Option<Route> {
+        let next: Option<Route> = route.next();
+        if next.is_none() {
+            println!(
+                "{} : dropped after {} hops, through {:?}",
+                cmd,
+                route.get_hops(),
+                route.get_trace()
+            );
+            requester.send_message(Error(
+                id,
+                format!(
+                    "{} dropped after {} hops",
+                    cmd.to_lowercase(),
+                    route.get_hops()
+                ),
+            ));
+        }
+        next
+    }
+
+    /// Tells whoever sent me a request from its location cache that I do not own `key`, the
+    /// request goes on to `next`.
+    fn report_misdirected(&self, route: &Route, key: i64, next: &Address) {
+        if let Some(sender) = route.get_cached_by() {
+            println!("CACHE : {:?} thinks I own {}", sender, key);
+            let previous: Option<i64> = self.range_before(key, next);
+            sender.send_message(Moved(key, self.addr.clone(), next.clone(), previous, None));
+        }
+    }
+
+    /// The start of the range of `next` when I know it owns `key`: it is then my successor,
+    /// and its range starts after me.
+    fn range_before(&self, key: i64, next: &Address) -> Option<i64> {
+        let owned: Interval = Interval::open_closed(self.addr.get_ring_id(), next.get_ring_id());
+        if *next == self.get_successor() && owned.contains(RingId::new(key)) {
+            Some(self.addr.get_id())
         } else {
             None
         }
     }
 
-    pub fn find_resp_in_table(&self, id: i64) -> Option<Address> {
-        let id: i64 = id % MAX_NODE;
-        return if self.is_mine(id) {
-            Some(self.addr.clone())
-        } else if let Some(a) = self.next_is_the_owner(id) {
-            Some(a)
-        } else {
-            let mut nearest_resp: Vec<(i64, Address)> = self
-                .association
-                .clone()
-                .into_iter()
-                .filter(|couple| couple.1.get_id() < id)
-                .collect();
-            nearest_resp.sort_by(|a, b| a.0.cmp(&b.0));
-            if !nearest_resp.is_empty() {
-                Some(nearest_resp[0].1.clone())
+    /// Gives a request for `key`, which I do not own, back to its requester with the node to
+    /// retry it at, the owner if I know it.
+    fn redirect_to(&self, requester: &Address, key: i64, target: &Address, request: Message) {
+        println!(
+            "REDIRECT : {} is not mine, {:?} should try {:?}",
+            key, requester, target
+        );
+        requester.send_message(Moved(
+            key,
+            self.addr.clone(),
+            target.clone(),
+            self.range_before(key, target),
+            Some(request.to_json()),
+        ));
+    }
+
+    /// Where to pass on a request about `key` that the routing sends to `next`: straight to
+    /// the owner when my location cache knows it and `next` is not already the owner.
+    fn via_cache(&mut self, key: i64, next: Address, route: Route) -> (Address, Route) {
+        let next_owns: Interval =
+            Interval::open_closed(self.addr.get_ring_id(), next.get_ring_id());
+        if next_owns.contains(RingId::new(key)) {
+            return (next, route);
+        }
+        match self.cache.get(key) {
+            Some(owner) if owner != self.addr && owner != next => {
+                (owner, route.cached_by(self.addr.clone()))
+            }
+            _ => (next, route),
+        }
+    }
+
+    /// Sends a write to the next node, or keeps it as a hint when that node is unreachable.
+    fn forward(&mut self, target: &Address, message: Message) {
+        if target.send_message(message.clone()).is_none() {
+            self.keep_hint(target, message);
+        }
+    }
+
+    /// Sends the puts of `writes` to the next node. When that node is unreachable they are
+    /// versioned now and kept as a `Hinted` message, so that replaying them later does not
+    /// override what was written in the meantime, and their versions are returned for the
+    /// requester to be answered.
+    fn forward_writes(
+        &mut self,
+        target: &Address,
+        message: Message,
+        writes: &[(i64, f64, Option<i64>)],
+    ) -> Option<HashMap<i64, Version>> {
+        if target.send_message(message).is_some() {
+            return None;
+        }
+        let now: i64 = now_millis();
+        let mut entries: HashMap<i64, Entry> = HashMap::new();
+        // the owner would refuse them
+        for &(key, value, ttl) in writes
+            .iter()
+            .filter(|(_, _, ttl)| !ttl.is_some_and(|t| t <= 0))
+        {
+            let expires_at: Option<i64> = ttl.map(|t| expiry(now, t));
+            entries.insert(key, Entry::new(value, self.next_version(), expires_at));
+        }
+        let versions: HashMap<i64, Version> =
+            entries.iter().map(|(&k, e)| (k, e.get_version())).collect();
+        if !entries.is_empty() {
+            let hinted: Message =
+                Hinted(self.addr.clone(), data_to_json(&entries), Route::new(false));
+            self.keep_hint(target, hinted);
+        }
+        Some(versions)
+    }
+
+    fn keep_hint(&mut self, target: &Address, message: Message) {
+        if self.hints.len() >= MAX_HINTS {
+            println!("HINT : too many hints, I drop the oldest one");
+            self.hints.remove(0);
+        }
+        println!("HINT : {:?} is unreachable, I keep the write", target);
+        self.hints.push(Hint {
+            target: target.clone(),
+            message: message.to_json(),
+            expires_at: now_millis() + HINT_TTL,
+        });
+    }
+
+    /// Replays the hints whose target answers again and forgets the expired ones.
+    fn replay_hints(&mut self) {
+        let now: i64 = now_millis();
+        let mut unreachable: Vec<i64> = Vec::new();
+        self.hints.retain(|h| {
+            if h.expires_at <= now {
+                println!("HINT : the write for {:?} expired", h.target);
+                return false;
+            }
+            // no need to knock again on a node that already failed during this replay
+            if unreachable.contains(&h.target.get_id()) {
+                return true;
+            }
+            if h.target.send_json(h.message.clone()).is_some() {
+                false
             } else {
-                None
+                unreachable.push(h.target.get_id());
+                true
+            }
+        });
+    }
+
+    /// Copies the given entries I own to my replicas, which acknowledge `request` if any.
+    fn replicate(&self, keys: &[i64], request: Option<i64>) {
+        let successor: Address = self.get_successor();
+        if self.replicas > 0 && successor != self.addr && !keys.is_empty() {
+            let entries: HashMap<i64, Entry> = keys
+                .iter()
+                .filter_map(|&k| self.data.get(k).map(|e| (k, e)))
+                .collect();
+            successor.send_message(Replicate(
+                self.addr.clone(),
+                data_to_json(&entries),
+                self.replicas,
+                request,
+            ));
+        }
+    }
+
+    /// Owner side of a `Get` with a consistency level above `One`: my copy counts as the first
+    /// answer and the replicas are asked for theirs.
+    fn read(&mut self, requester: Address, key: i64, consistency: Consistency, route: Route) {
+        let needed: i64 = consistency.required(self.replicas + 1);
+        let entry: Option<Entry> = self.lookup(key);
+        if needed <= 1 {
+            self.reply_read(&requester, key, entry, route);
+            return;
+        }
+        let request: i64 = self.start_request(requester, key, None, needed, route);
+        if let Some(p) = self.requests.get_mut(&request) {
+            p.answers[0].1 = entry;
+        }
+        let successor: Address = self.get_successor();
+        if successor != self.addr {
+            successor.send_message(ReplicaRead(self.addr.clone(), request, key, self.replicas));
+        }
+    }
+
+    /// Registers a request of the owner, which is already answered by its own copy.
+    fn start_request(
+        &mut self,
+        requester: Address,
+        key: i64,
+        write_id: Option<i64>,
+        needed: i64,
+        route: Route,
+    ) -> i64 {
+        self.next_request += 1;
+        self.requests.insert(
+            self.next_request,
+            Pending {
+                requester,
+                key,
+                write_id,
+                needed,
+                answers: vec![(self.addr.clone(), None)],
+                repaired: 0,
+                done: false,
+                started: now_millis(),
+                route,
+            },
+        );
+        self.next_request
+    }
+
+    /// Replies to the requester once enough copies answered. A read returns the newest of the
+    /// copies and then brings the stale ones, including mine, up to date.
+    fn settle(&mut self, request: i64) {
+        let p: &mut Pending = match self.requests.get_mut(&request) {
+            Some(p) => p,
+            None => return,
+        };
+        let enough: bool = p.answers.len() as i64 >= p.needed;
+        let newest: Option<Entry> = p
+            .answers
+            .iter()
+            .filter_map(|(_, e)| e.clone())
+            .max_by_key(|e| e.get_version());
+        if let Some(id) = p.write_id {
+            if enough {
+                p.requester.send_message(Ack(id, p.route.clone()));
+                self.requests.remove(&request);
             }
+            return;
+        }
+        if !enough {
+            return;
+        }
+        let key: i64 = p.key;
+        let first_answer: bool = !p.done;
+        p.done = true;
+        let stale: Vec<Address> = p.answers[p.repaired..]
+            .iter()
+            .filter(|(_, e)| match (e, &newest) {
+                (Some(e), Some(newest)) => newest.is_newer_than(e),
+                (None, Some(_)) => true,
+                _ => false,
+            })
+            .map(|(a, _)| a.clone())
+            .collect();
+        p.repaired = p.answers.len();
+        let complete: bool = p.answers.len() as i64 > self.replicas;
+        let requester: Address = p.requester.clone();
+        let route: Route = p.route.clone();
+        if first_answer {
+            self.reply_read(&requester, key, newest.clone(), route);
+        }
+        if let Some(newest) = newest {
+            for replica in stale {
+                println!("READ REPAIR : {:?} has an old copy of {}", replica, key);
+                if replica == self.addr {
+                    self.merge_entry(key, newest.clone());
+                } else {
+                    let mut data: HashMap<i64, Entry> = HashMap::new();
+                    data.insert(key, newest.clone());
+                    replica.send_message(Replicate(
+                        self.addr.clone(),
+                        data_to_json(&data),
+                        1,
+                        None,
+                    ));
+                }
+            }
+        }
+        if complete {
+            self.requests.remove(&request);
+        }
+    }
+
+    fn reply_read(&self, requester: &Address, key: i64, entry: Option<Entry>, route: Route) {
+        match entry {
+            Some(e) if *requester == self.addr => {
+                println!("{} (version {:?})", e.get_value(), e.get_version());
+            }
+            Some(e) => {
+                requester.send_message(Answer(
+                    key,
+                    e.get_value(),
+                    true,
+                    e.get_version(),
+                    Some(route),
+                ));
+            }
+            None => {
+                requester.send_message(Answer(
+                    key,
+                    0.0,
+                    false,
+                    Version::new(0, self.addr.get_id()),
+                    Some(route),
+                ));
+            }
+        }
+    }
+
+    /// Forgets the requests that waited too long, the ones never answered fail.
+    fn expire_requests(&mut self, now: i64) {
+        let expired: Vec<i64> = self
+            .requests
+            .iter()
+            .filter(|(_, p)| now - p.started >= REQUEST_TIMEOUT)
+            .map(|(&r, _)| r)
+            .collect();
+        for request in expired {
+            if let Some(p) = self.requests.remove(&request) {
+                if !p.done {
+                    println!(
+                        "QUORUM : request {} only got {} answers",
+                        request,
+                        p.answers.len()
+                    );
+                    let reason: String = format!(
+                        "only {} of the {} copies needed for key {} answered",
+                        p.answers.len(),
+                        p.needed,
+                        p.key
+                    );
+                    p.requester
+                        .send_message(Error(p.write_id.unwrap_or(p.key), reason));
+                }
+            }
+        }
+    }
+
+    /// The copies I keep of the entries owned by the node whose range is `(lower, upper]`.
+    fn replica_copy(&self, lower: i64, upper: i64) -> Vec<(i64, Entry)> {
+        let range: Interval = Interval::open_closed(RingId::new(lower), RingId::new(upper));
+        self.replica_data
+            .iter()
+            .filter(|(&k, _)| range.contains(RingId::new(k)))
+            .map(|(&k, e)| (k, e.clone()))
+            .collect()
+    }
+
+    /// Sends `found` to `addr` in pages of `SCAN_PAGE_SIZE` entries, `last` tells whether
+    /// this node is the last one to answer. The last node always sends at least one page so
+    /// the requester knows the walk is over.
+    fn send_pages(
+        &self,
+        addr: &Address,
+        found: &[(i64, Entry)],
+        last: bool,
+        page_message: fn(i64, Value, bool) -> Message,
+    ) {
+        let pages: Vec<&[(i64, Entry)]> = found.chunks(SCAN_PAGE_SIZE).collect();
+        for (idx, page) in pages.iter().enumerate() {
+            let entries: Value = Value::Array(page.iter().map(|(k, e)| e.to_json(*k)).collect());
+            addr.send_message(page_message(
+                self.addr.get_id(),
+                entries,
+                last && idx + 1 == pages.len(),
+            ));
+        }
+        if last && pages.is_empty() {
+            addr.send_message(page_message(self.addr.get_id(), Value::Array(vec![]), true));
+        }
+    }
+
+    /// Writes `key` as the owner: the write gets a fresh version from the clock.
+    fn store(&mut self, key: i64, value: f64, ttl: Option<i64>) -> Version {
+        let version: Version = self.next_version();
+        let expires_at: Option<i64> = ttl.map(|t| expiry(now_millis(), t));
+        self.data.put(key, Entry::new(value, version, expires_at));
+        version
+    }
+
+    /// Adds `delta` to the current value of `key` (a missing key counts as 0) and keeps its
+    /// expiry, the owner handles messages one at a time so no other write can interleave.
+    fn increment(&mut self, key: i64, delta: f64) -> Entry {
+        let (value, expires_at): (f64, Option<i64>) = match self.lookup(key) {
+            Some(e) => (e.get_value() + delta, e.get_expires_at()),
+            None => (delta, None),
+        };
+        let entry: Entry = Entry::new(value, self.next_version(), expires_at);
+        self.data.put(key, entry.clone());
+        entry
+    }
+
+    /// A version past every one I gave or merged, and never behind the wall clock.
+    fn next_version(&mut self) -> Version {
+        self.clock = (self.clock + 1).max(now_millis());
+        Version::new(self.clock, self.addr.get_id())
+    }
+
+    fn lookup(&self, key: i64) -> Option<Entry> {
+        let now: i64 = now_millis();
+        self.data.get(key).filter(|e| !e.is_expired(now))
+    }
+
+    /// Splits a batch between the items whose key this node owns and, for every other next
+    /// hop, the group of items that has to be forwarded to it in a single message.
+    fn split_batch<F>(&self, items: &[Value], key_of: F) -> (Vec<Value>, Vec<(Address, Vec<Value>)>)
+    where
+        F: Fn(&Value) -> Option<i64>,
+    {
+        let mut mine: Vec<Value> = Vec::new();
+        let mut others: HashMap<i64, (Address, Vec<Value>)> = HashMap::new();
+        for item in items {
+            if let Some(n) = key_of(item).and_then(|key| self.find_resp_in_table(key)) {
+                if n.get_id() == self.addr.get_id() {
+                    mine.push(item.clone());
+                } else {
+                    others
+                        .entry(n.get_id())
+                        .or_insert_with(|| (n, Vec::new()))
+                        .1
+                        .push(item.clone());
+                }
+            }
+        }
+        (mine, others.into_values().collect())
+    }
+
+    /// Keeps the newest of the local and the incoming copy of `key`, according to their
+    /// versions, and moves the clock past the incoming write.
+    fn merge_entry(&mut self, key: i64, entry: Entry) {
+        self.clock = self.clock.max(entry.get_version().get_counter());
+        let is_newer: bool = match self.data.get(key) {
+            Some(current) => entry.is_newer_than(&current),
+            None => true,
         };
+        if is_newer {
+            self.data.put(key, entry);
+        }
+    }
+
+    fn is_mine(&self, id: i64) -> bool {
+        let mine: Interval =
+            Interval::open_closed(self.previous.get_ring_id(), self.addr.get_ring_id());
+        mine.contains(RingId::new(id))
+    }
+    pub fn get_successor(&self) -> Address {
+        match self.association.get(&(self.addr.get_ring_id() + 1).get()) {
+            Some(a) => a.clone(),
+            None => self.addr.clone(),
+        }
+    }
+
+    /// Tells whether the scan of `[start, end]` is complete once this node has answered.
+    /// The node which owns `start` (`origin`) only covers the positions from `start` up to its
+    /// own id, every other node covers its whole interval.
+    fn scan_ends_here(&self, start: i64, end: i64, origin: i64) -> bool {
+        let start_position: RingId = RingId::new(start);
+        let end_position: RingId = RingId::new(end);
+        if end.saturating_sub(start) >= MAX_NODE {
+            false
+        } else if self.addr.get_id() == origin {
+            start_position.distance(end_position)
+                <= start_position.distance(self.addr.get_ring_id())
+        } else {
+            self.is_mine(end_position.get())
+        }
+    }
+
+    fn closest_preceding(&self, id: i64) -> Vec<Address> {
+        closest_preceding(&self.addr, &self.association, RingId::new(id))
+    }
+
+    /// Next node a message about the key (or node id) `id` goes to, myself when I own it.
+    pub fn find_resp_in_table(&self, id: i64) -> Option<Address> {
+        next_hop(&self.addr, self.previous.get_id(), &self.association, id)
     }
 }
@@ -0,0 +1,113 @@
+use crate::chord::node::MAX_NODE;
+use std::ops::{Add, Sub};
+
+/// A position on a ring of `SIZE` positions, node ids and key positions alike. Every
+/// operation wraps around the ring, a position is always in `[0, SIZE)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RingId<const SIZE: i64 = MAX_NODE> {
+    value: i64,
+}
+
+impl<const SIZE: i64> RingId<SIZE> {
+    pub fn new(value: i64) -> RingId<SIZE> {
+        RingId {
+            value: value.rem_euclid(SIZE),
+        }
+    }
+
+    pub fn get(self) -> i64 {
+        self.value
+    }
+
+    /// Number of steps going clockwise from `self` to `other`, in `[0, SIZE)`.
+    pub fn distance(self, other: RingId<SIZE>) -> i64 {
+        (other.value - self.value).rem_euclid(SIZE)
+    }
+}
+
+impl<const SIZE: i64> From<i64> for RingId<SIZE> {
+    fn from(value: i64) -> RingId<SIZE> {
+        RingId::new(value)
+    }
+}
+
+impl<const SIZE: i64> Add<i64> for RingId<SIZE> {
+    type Output = RingId<SIZE>;
+
+    fn add(self, steps: i64) -> RingId<SIZE> {
+        RingId::new(self.value + steps)
+    }
+}
+
+impl<const SIZE: i64> Sub<i64> for RingId<SIZE> {
+    type Output = RingId<SIZE>;
+
+    fn sub(self, steps: i64) -> RingId<SIZE> {
+        RingId::new(self.value - steps)
+    }
+}
+
+/// The positions met going clockwise from `lower` to `upper`, each bound being included or
+/// not. When both bounds are the same position the interval goes once around the ring: `(a, a]`
+/// is the whole ring, which a node alone owns, and `(a, a)` every position but `a`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval<const SIZE: i64 = MAX_NODE> {
+    lower: RingId<SIZE>,
+    upper: RingId<SIZE>,
+    lower_closed: bool,
+    upper_closed: bool,
+}
+
+impl<const SIZE: i64> Interval<SIZE> {
+    pub fn new(
+        lower: RingId<SIZE>,
+        upper: RingId<SIZE>,
+        lower_closed: bool,
+        upper_closed: bool,
+    ) -> Interval<SIZE> {
+        Interval {
+            lower,
+            upper,
+            lower_closed,
+            upper_closed,
+        }
+    }
+
+    /// `(lower, upper)`
+    pub fn open(lower: RingId<SIZE>, upper: RingId<SIZE>) -> Interval<SIZE> {
+        Interval::new(lower, upper, false, false)
+    }
+
+    /// `[lower, upper]`
+    pub fn closed(lower: RingId<SIZE>, upper: RingId<SIZE>) -> Interval<SIZE> {
+        Interval::new(lower, upper, true, true)
+    }
+
+    /// `(lower, upper]`, the positions owned by the node `upper` when its previous node is
+    /// `lower`.
+    pub fn open_closed(lower: RingId<SIZE>, upper: RingId<SIZE>) -> Interval<SIZE> {
+        Interval::new(lower, upper, false, true)
+    }
+
+    /// `[lower, upper)`
+    pub fn closed_open(lower: RingId<SIZE>, upper: RingId<SIZE>) -> Interval<SIZE> {
+        Interval::new(lower, upper, true, false)
+    }
+
+    /// Number of steps from `lower` to `upper`, a whole turn when they are the same.
+    pub fn width(&self) -> i64 {
+        match self.lower.distance(self.upper) {
+            0 => SIZE,
+            w => w,
+        }
+    }
+
+    pub fn contains(&self, id: RingId<SIZE>) -> bool {
+        let steps: i64 = match self.lower.distance(id) {
+            // an open lower bound is only met again after a whole turn
+            0 if !self.lower_closed => SIZE,
+            d => d,
+        };
+        steps < self.width() || (steps == self.width() && self.upper_closed)
+    }
+}
@@ -0,0 +1,96 @@
+use crate::chord::address::Address;
+use crate::chord::node::{get_addr_from_json, MAX_NODE};
+use serde_json::{json, Value};
+
+/// Number of times a message may be forwarded: a lookup never visits more nodes than the ring
+/// can hold and a scan then walks the ring at most once, a message still travelling after
+/// that is looping.
+pub const ROUTE_TTL: i64 = 2 * MAX_NODE;
+
+/// The way a routed message went: how many times it was forwarded, how many more times it may
+/// be, and when it is traced the ids of the nodes which handled it, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    hops: i64,
+    ttl: i64,
+    trace: Option<Vec<i64>>,
+    /// who sent the message straight to the owner found in its location cache
+    cached_by: Option<Address>,
+}
+
+impl Route {
+    pub fn new(traced: bool) -> Route {
+        Route {
+            hops: 0,
+            ttl: ROUTE_TTL,
+            trace: if traced { Some(Vec::new()) } else { None },
+            cached_by: None,
+        }
+    }
+
+    /// The route of a message `sender` sends to the owner found in its location cache, which
+    /// tells the sender when it is not the owner anymore.
+    pub fn cached_by(mut self, sender: Address) -> Route {
+        self.cached_by = Some(sender);
+        self
+    }
+
+    pub fn get_hops(&self) -> i64 {
+        self.hops
+    }
+
+    pub fn get_ttl(&self) -> i64 {
+        self.ttl
+    }
+
+    pub fn get_trace(&self) -> Option<Vec<i64>> {
+        self.trace.clone()
+    }
+
+    pub fn get_cached_by(&self) -> Option<Address> {
+        self.cached_by.clone()
+    }
+
+    /// The route once the node `id` handled the message.
+    pub fn through(mut self, id: i64) -> Route {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(id);
+        }
+        self
+    }
+
+    /// The route to give the next node, `None` when the message may not be forwarded anymore.
+    /// The next node is chosen by routing, not by a cache.
+    pub fn next(&self) -> Option<Route> {
+        if self.ttl <= 0 {
+            return None;
+        }
+        Some(Route {
+            hops: self.hops.saturating_add(1),
+            ttl: self.ttl - 1,
+            trace: self.trace.clone(),
+            cached_by: None,
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({"hops" : self.hops, "ttl" : self.ttl, "trace" : self.trace, "cached_by" : self.cached_by.as_ref().map(|a| a.to_json())})
+    }
+
+    /// Messages sent without a route, by older clients, start an untraced one.
+    pub fn from_json(json_obj: &Value) -> Route {
+        let mut route: Route = Route::new(false);
+        if let Some(hops) = json_obj["hops"].as_i64() {
+            route.hops = hops;
+        }
+        // a sender can't buy a message more hops than any lookup needs
+        if let Some(ttl) = json_obj["ttl"].as_i64() {
+            route.ttl = ttl.min(ROUTE_TTL);
+        }
+        route.trace = json_obj["trace"]
+            .as_array()
+            .map(|ids| ids.iter().filter_map(|id| id.as_i64()).collect());
+        route.cached_by = get_addr_from_json(json_obj, "cached_by");
+        route
+    }
+}
@@ -0,0 +1,57 @@
+use serde_json::Value;
+use std::fs::{rename, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Snapshot of the routing state of a node (previous node, finger table and a bound of its
+/// clock), rewritten atomically and synced every time it changes so that a crashed node can
+/// rejoin the ring where it was. The entries themselves are made durable by the node's storage.
+#[derive(Debug)]
+pub struct StateFile {
+    path: PathBuf,
+    last: String,
+}
+
+impl StateFile {
+    pub fn new(path: PathBuf) -> StateFile {
+        StateFile {
+            path,
+            last: String::new(),
+        }
+    }
+
+    /// The last snapshot written before the node stopped, if any.
+    pub fn load(&self) -> Option<Value> {
+        let mut content: String = String::new();
+        File::open(&self.path)
+            .and_then(|mut f| f.read_to_string(&mut content))
+            .ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn save(&mut self, state: &Value) {
+        let content: String = state.to_string();
+        if content == self.last {
+            return;
+        }
+        match self.replace(&content) {
+            Ok(_) => self.last = content,
+            Err(e) => println!("can't save the state in {:?} : {}", self.path, e),
+        }
+    }
+
+    /// Writes `content` in a temporary file renamed over the state, each synced so that a crash
+    /// leaves either the old state or the new one.
+    fn replace(&self, content: &str) -> std::io::Result<()> {
+        let tmp_path: PathBuf = self.path.with_extension("tmp");
+        let mut tmp: File = File::create(&tmp_path)?;
+        tmp.write_all(content.as_bytes())?;
+        tmp.sync_all()?;
+        rename(&tmp_path, &self.path)?;
+        let dir: &Path = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()
+    }
+}
@@ -0,0 +1,421 @@
+use crate::chord::entry::Entry;
+use crate::chord::ring::{Interval, RingId};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs::{rename, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Number of obsolete records tolerated in a log before it gets compacted.
+const COMPACTION_THRESHOLD: usize = 1024;
+
+/// Where a node keeps the entries it owns.
+pub trait Storage: Debug + Send {
+    fn get(&self, key: i64) -> Option<Entry>;
+
+    fn put(&mut self, key: i64, entry: Entry);
+
+    fn delete(&mut self, key: i64) -> Option<Entry>;
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (i64, Entry)> + '_>;
+
+    fn keys(&self) -> Vec<i64> {
+        self.iter().map(|(k, _)| k).collect()
+    }
+
+    /// Entries whose key is in `[start, end]`, sorted by key.
+    fn range(&self, start: i64, end: i64) -> Vec<(i64, Entry)> {
+        let mut found: Vec<(i64, Entry)> = self
+            .iter()
+            .filter(|(k, _)| *k >= start && *k <= end)
+            .collect();
+        found.sort_unstable_by_key(|(k, _)| *k);
+        found
+    }
+
+    /// Keys whose ring position is in `(lower, upper]`, that is the keys a node joining as
+    /// `upper` after `lower` becomes responsible for.
+    fn keys_in_range(&self, lower: i64, upper: i64) -> Vec<i64> {
+        let range: Interval = Interval::open_closed(RingId::new(lower), RingId::new(upper));
+        self.keys()
+            .into_iter()
+            .filter(|&k| range.contains(RingId::new(k)))
+            .collect()
+    }
+
+    /// Removes and returns the entries of `keys_in_range(lower, upper)`.
+    fn extract_range(&mut self, lower: i64, upper: i64) -> Vec<(i64, Entry)> {
+        self.keys_in_range(lower, upper)
+            .into_iter()
+            .filter_map(|k| self.delete(k).map(|e| (k, e)))
+            .collect()
+    }
+
+    /// Called periodically by the node, lets a storage reclaim space.
+    fn compact(&mut self) {}
+}
+
+impl Storage for Box<dyn Storage> {
+    fn get(&self, key: i64) -> Option<Entry> {
+        (**self).get(key)
+    }
+
+    fn put(&mut self, key: i64, entry: Entry) {
+        (**self).put(key, entry)
+    }
+
+    fn delete(&mut self, key: i64) -> Option<Entry> {
+        (**self).delete(key)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (i64, Entry)> + '_> {
+        (**self).iter()
+    }
+
+    fn keys(&self) -> Vec<i64> {
+        (**self).keys()
+    }
+
+    fn range(&self, start: i64, end: i64) -> Vec<(i64, Entry)> {
+        (**self).range(start, end)
+    }
+
+    fn keys_in_range(&self, lower: i64, upper: i64) -> Vec<i64> {
+        (**self).keys_in_range(lower, upper)
+    }
+
+    fn extract_range(&mut self, lower: i64, upper: i64) -> Vec<(i64, Entry)> {
+        (**self).extract_range(lower, upper)
+    }
+
+    fn compact(&mut self) {
+        (**self).compact()
+    }
+}
+
+/// A storage shared by the virtual nodes of a server, every handle sees the same entries.
+/// Each node only hands out the keys of its own interval, so iterating over the others' keys
+/// is harmless.
+#[derive(Debug, Clone)]
+pub struct SharedStorage {
+    inner: Arc<Mutex<Box<dyn Storage>>>,
+}
+
+impl SharedStorage {
+    pub fn new(storage: Box<dyn Storage>) -> SharedStorage {
+        SharedStorage {
+            inner: Arc::new(Mutex::new(storage)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Box<dyn Storage>> {
+        // the virtual nodes run on one thread, a poisoned lock only means a node panicked
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Storage for SharedStorage {
+    fn get(&self, key: i64) -> Option<Entry> {
+        self.lock().get(key)
+    }
+
+    fn put(&mut self, key: i64, entry: Entry) {
+        self.lock().put(key, entry)
+    }
+
+    fn delete(&mut self, key: i64) -> Option<Entry> {
+        self.lock().delete(key)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (i64, Entry)> + '_> {
+        let entries: Vec<(i64, Entry)> = self.lock().iter().collect();
+        Box::new(entries.into_iter())
+    }
+
+    fn keys(&self) -> Vec<i64> {
+        self.lock().keys()
+    }
+
+    fn range(&self, start: i64, end: i64) -> Vec<(i64, Entry)> {
+        self.lock().range(start, end)
+    }
+
+    fn keys_in_range(&self, lower: i64, upper: i64) -> Vec<i64> {
+        self.lock().keys_in_range(lower, upper)
+    }
+
+    fn extract_range(&mut self, lower: i64, upper: i64) -> Vec<(i64, Entry)> {
+        self.lock().extract_range(lower, upper)
+    }
+
+    fn compact(&mut self) {
+        self.lock().compact()
+    }
+}
+
+/// Keeps everything in memory, the content is lost when the node stops.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    data: HashMap<i64, Entry>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage {
+            data: HashMap::new(),
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: i64) -> Option<Entry> {
+        self.data.get(&key).cloned()
+    }
+
+    fn put(&mut self, key: i64, entry: Entry) {
+        self.data.insert(key, entry);
+    }
+
+    fn delete(&mut self, key: i64) -> Option<Entry> {
+        self.data.remove(&key)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (i64, Entry)> + '_> {
+        Box::new(self.data.iter().map(|(&k, e)| (k, e.clone())))
+    }
+
+    fn keys(&self) -> Vec<i64> {
+        self.data.keys().copied().collect()
+    }
+}
+
+/// Keeps the entries in memory and appends every change to a log file, one json record per
+/// line. The log is replayed when the storage is opened and rewritten with only the live
+/// entries once it holds too many obsolete records.
+#[derive(Debug)]
+pub struct LogStorage {
+    path: PathBuf,
+    log: File,
+    data: HashMap<i64, Entry>,
+    obsolete: usize,
+}
+
+impl LogStorage {
+    pub fn open(path: PathBuf) -> std::io::Result<LogStorage> {
+        let mut data: HashMap<i64, Entry> = HashMap::new();
+        let mut obsolete: usize = 0;
+        // length of the log up to the end of its last whole record
+        let mut valid_len: u64 = 0;
+        if let Ok(f) = File::open(&path) {
+            let mut reader: BufReader<File> = BufReader::new(f);
+            let mut line: Vec<u8> = Vec::new();
+            loop {
+                line.clear();
+                let read: usize = reader.read_until(b'\n', &mut line)?;
+                // a record cut by a crash has no end of line or is not json, everything
+                // before it is still valid
+                if line.last() != Some(&b'\n') {
+                    break;
+                }
+                let record: Value = match serde_json::from_slice(&line) {
+                    Ok(v) => v,
+                    Err(_) => break,
+                };
+                valid_len += read as u64;
+                let replaced: bool = match (record["op"].as_str(), record["key"].as_i64()) {
+                    (Some("put"), _) => match Entry::from_json(&record) {
+                        Some((key, entry)) => data.insert(key, entry).is_some(),
+                        None => false,
+                    },
+                    (Some("remove"), Some(key)) => {
+                        obsolete += 1;
+                        data.remove(&key).is_some()
+                    }
+                    _ => false,
+                };
+                if replaced {
+                    obsolete += 1;
+                }
+            }
+        }
+        let log: File = OpenOptions::new().create(true).append(true).open(&path)?;
+        if log.metadata()?.len() > valid_len {
+            // the next records must not be glued to the torn one, which would hide them
+            println!(
+                "{:?} : dropping a torn record after {} bytes",
+                path, valid_len
+            );
+            log.set_len(valid_len)?;
+        }
+        let mut storage: LogStorage = LogStorage {
+            path,
+            log,
+            data,
+            obsolete,
+        };
+        storage.compact();
+        Ok(storage)
+    }
+
+    fn append(&mut self, record: Value) {
+        if let Err(e) = writeln!(self.log, "{}", record).and_then(|_| self.log.flush()) {
+            println!("can't write in {:?} : {}", self.path, e);
+        }
+    }
+
+    fn rewrite(&mut self) -> std::io::Result<()> {
+        let tmp_path: PathBuf = self.path.with_extension("compact");
+        let mut tmp: File = File::create(&tmp_path)?;
+        for (&key, entry) in self.data.iter() {
+            let mut record: Value = entry.to_json(key);
+            record["op"] = json!("put");
+            writeln!(tmp, "{}", record)?;
+        }
+        tmp.sync_all()?;
+        rename(&tmp_path, &self.path)?;
+        self.log = OpenOptions::new().append(true).open(&self.path)?;
+        self.obsolete = 0;
+        Ok(())
+    }
+}
+
+impl Storage for LogStorage {
+    fn get(&self, key: i64) -> Option<Entry> {
+        self.data.get(&key).cloned()
+    }
+
+    fn put(&mut self, key: i64, entry: Entry) {
+        let mut record: Value = entry.to_json(key);
+        record["op"] = json!("put");
+        self.append(record);
+        if self.data.insert(key, entry).is_some() {
+            self.obsolete += 1;
+        }
+    }
+
+    fn delete(&mut self, key: i64) -> Option<Entry> {
+        let removed: Option<Entry> = self.data.remove(&key);
+        if removed.is_some() {
+            self.append(json!({"op" : "remove", "key" : key}));
+            self.obsolete += 2;
+        }
+        removed
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (i64, Entry)> + '_> {
+        Box::new(self.data.iter().map(|(&k, e)| (k, e.clone())))
+    }
+
+    fn keys(&self) -> Vec<i64> {
+        self.data.keys().copied().collect()
+    }
+
+    fn compact(&mut self) {
+        if self.obsolete > COMPACTION_THRESHOLD.max(self.data.len()) {
+            if let Err(e) = self.rewrite() {
+                println!("can't compact {:?} : {}", self.path, e);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "sled")]
+pub use self::sled_backend::SledStorage;
+
+#[cfg(feature = "sled")]
+mod sled_backend {
+    use super::Storage;
+    use crate::chord::entry::Entry;
+    use serde_json::Value;
+    use std::fmt;
+    use std::path::PathBuf;
+
+    /// Keeps the entries in a sled database. Keys are stored big-endian with the sign bit
+    /// flipped so that sled's byte order is the numeric order and ranges are native scans.
+    pub struct SledStorage {
+        path: PathBuf,
+        db: sled::Db,
+    }
+
+    impl fmt::Debug for SledStorage {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "SledStorage {{ path: {:?}, len: {} }}",
+                self.path,
+                self.db.len()
+            )
+        }
+    }
+
+    fn encode(key: i64) -> [u8; 8] {
+        ((key as u64) ^ (1 << 63)).to_be_bytes()
+    }
+
+    fn decode(key: &[u8], value: &[u8]) -> Option<(i64, Entry)> {
+        let mut bytes: [u8; 8] = [0; 8];
+        bytes.copy_from_slice(key.get(0..8)?);
+        let key: i64 = (u64::from_be_bytes(bytes) ^ (1 << 63)) as i64;
+        let v: Value = serde_json::from_slice(value).ok()?;
+        Entry::from_json(&v).filter(|(k, _)| *k == key)
+    }
+
+    impl SledStorage {
+        pub fn open(path: PathBuf) -> sled::Result<SledStorage> {
+            let db: sled::Db = sled::open(&path)?;
+            Ok(SledStorage { path, db })
+        }
+    }
+
+    impl Storage for SledStorage {
+        fn get(&self, key: i64) -> Option<Entry> {
+            match self.db.get(encode(key)) {
+                Ok(Some(v)) => decode(&encode(key), &v).map(|(_, e)| e),
+                _ => None,
+            }
+        }
+
+        fn put(&mut self, key: i64, entry: Entry) {
+            let value: String = entry.to_json(key).to_string();
+            if let Err(e) = self.db.insert(encode(key), value.as_bytes()) {
+                println!("can't write in {:?} : {}", self.path, e);
+            }
+        }
+
+        fn delete(&mut self, key: i64) -> Option<Entry> {
+            match self.db.remove(encode(key)) {
+                Ok(Some(v)) => decode(&encode(key), &v).map(|(_, e)| e),
+                Ok(None) => None,
+                Err(e) => {
+                    println!("can't write in {:?} : {}", self.path, e);
+                    None
+                }
+            }
+        }
+
+        fn iter(&self) -> Box<dyn Iterator<Item = (i64, Entry)> + '_> {
+            Box::new(
+                self.db
+                    .iter()
+                    .filter_map(|r| r.ok())
+                    .filter_map(|(k, v)| decode(&k, &v)),
+            )
+        }
+
+        fn range(&self, start: i64, end: i64) -> Vec<(i64, Entry)> {
+            self.db
+                .range(encode(start)..=encode(end))
+                .filter_map(|r| r.ok())
+                .filter_map(|(k, v)| decode(&k, &v))
+                .collect()
+        }
+
+        fn compact(&mut self) {
+            if let Err(e) = self.db.flush() {
+                println!("can't flush {:?} : {}", self.path, e);
+            }
+        }
+    }
+}
@@ -1,18 +1,236 @@
-mod app;
-mod chord;
-use app::client::parameter::{get_args, Param};
-use chord::address::Address;
-use chord::message::Message::{Exit, Get, Put};
+use copper::app::client::parameter::{get_args, Param};
+use copper::chord::address::Address;
+use copper::chord::cache::{LocationCache, LOCATION_CACHE_SIZE};
+use copper::chord::consistency::Consistency;
+use copper::chord::entry::{import_entries, now_millis, Version};
+use copper::chord::lookup::{locate, lookup_iterative, Location};
+use copper::chord::message::Message::{
+    Dump, Exit, Get, Incr, MultiGet, MultiPut, Put, Rebalance, Scan,
+};
+use copper::chord::node::{get_addr_from_json, read_parse};
+use copper::chord::route::Route;
 use rand::Rng;
-use serde_json::Value;
-use std::io::{stdin, stdout, Read, Stdin, Write};
-use std::net::TcpListener;
-use std::num::ParseIntError;
-use std::ops::Add;
-use std::sync::mpsc;
-use std::sync::mpsc::TryRecvError;
-use std::sync::Arc;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read_to_string, File};
+use std::io::{stdin, stdout, Write};
+use std::net::{Ipv4Addr, TcpListener};
+use std::sync::mpsc::Receiver;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
 use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Number of entries sent in each `MultiPut` of an import.
+const IMPORT_BATCH: usize = 1000;
+
+/// How long an iterative lookup waits for each node to answer.
+const HOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a batch waits for the next answer of its owners before reporting the keys left
+/// unanswered.
+const BATCH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How get and put find the owner of their key.
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    /// the entry node routes the request
+    Recursive,
+    /// the client asks the nodes one by one, then sends to the owner
+    Iterative,
+    /// the client sends to the owner remembered from a previous lookup
+    Cached,
+}
+
+/// What the prompt tells the thread receiving the answers.
+enum Event {
+    Stop,
+    Export(File),
+}
+
+/// Appends the entries of a dump page to the export file, one json entry per line.
+fn export_page(file: &mut File, args: &Value) {
+    let entries: &[Value] = match args["entries"].as_array() {
+        Some(entries) => entries,
+        None => return,
+    };
+    for e in entries {
+        if let Err(err) = writeln!(file, "{}", e) {
+            println!("can't write the export : {}", err);
+            return;
+        }
+    }
+    println!(
+        "exported {} entries from node {}",
+        entries.len(),
+        args["id"]
+    );
+    if args["last"].as_bool() == Some(true) {
+        println!("export done");
+    }
+}
+
+/// Where to send a request about `key`: the entry node, which routes it, in recursive mode,
+/// the owner found by an iterative lookup in iterative mode, the owner in the location cache
+/// in cached mode, which is located through the entry node when missing.
+fn destination(
+    addr_d: &Address,
+    ip: Ipv4Addr,
+    key: i64,
+    mode: Mode,
+    cache: &Mutex<LocationCache>,
+) -> Option<Address> {
+    let owner: Option<Address> = match mode {
+        Mode::Recursive => return Some(addr_d.clone()),
+        Mode::Iterative => lookup_iterative(addr_d, ip, key, HOP_TIMEOUT).map(|l| l.get_owner()),
+        Mode::Cached => {
+            let mut cache: MutexGuard<LocationCache> =
+                cache.lock().unwrap_or_else(|e| e.into_inner());
+            match cache.get(key) {
+                Some(owner) => Some(owner),
+                None => locate(addr_d, ip, key, HOP_TIMEOUT).map(|l| {
+                    cache.insert(l.get_previous(), l.get_owner());
+                    l.get_owner()
+                }),
+            }
+        }
+    };
+    if owner.is_none() {
+        println!("no owner found for key {}", key);
+    }
+    owner
+}
+
+/// Gathers the `MultiAnswer`s to the batches `ids` until every key of `keys` has a result or
+/// no answer came for `BATCH_TIMEOUT`. Returns the result of each answered key and the keys
+/// left unanswered, whose owner could not be reached.
+fn gather(
+    answers: &Receiver<Value>,
+    ids: &[i64],
+    keys: &[i64],
+) -> (BTreeMap<i64, Value>, Vec<i64>) {
+    let wanted: BTreeSet<i64> = keys.iter().copied().collect();
+    let mut results: BTreeMap<i64, Value> = BTreeMap::new();
+    while results.len() < wanted.len() {
+        let answer: Value = match answers.recv_timeout(BATCH_TIMEOUT) {
+            Ok(answer) => answer,
+            Err(_) => break,
+        };
+        // answers to an earlier batch that timed out are dropped
+        if !answer["id"].as_i64().is_some_and(|id| ids.contains(&id)) {
+            continue;
+        }
+        if let Some(found) = answer["results"].as_object() {
+            for (key, result) in found {
+                if let Some(key) = key.parse::<i64>().ok().filter(|k| wanted.contains(k)) {
+                    results.insert(key, result.clone());
+                }
+            }
+        }
+    }
+    let unanswered: Vec<i64> = wanted
+        .into_iter()
+        .filter(|k| !results.contains_key(k))
+        .collect();
+    (results, unanswered)
+}
+
+/// What happened to a key of a batch, as its owner answered.
+fn describe(result: &Value) -> String {
+    let version: String = match Version::from_json(&result["version"]) {
+        Some(v) => format!(" (version {:?})", v),
+        None => String::new(),
+    };
+    if let Some(reason) = result["error"].as_str() {
+        format!("refused : {}", reason)
+    } else if result["value_exists"].as_bool() == Some(false) {
+        "missing".to_string()
+    } else if result["hinted"].as_bool() == Some(true) {
+        format!("kept until its owner answers{}", version)
+    } else if let Some(value) = result["value"].as_f64() {
+        format!("{}{}", value, version)
+    } else {
+        format!("written{}", version)
+    }
+}
+
+/// Prints the result of every key of a batch, then the keys nobody answered for.
+fn report_batch(answers: &Receiver<Value>, id: i64, keys: &[i64]) {
+    let (results, unanswered): (BTreeMap<i64, Value>, Vec<i64>) = gather(answers, &[id], keys);
+    for (key, result) in results.iter() {
+        println!("key {} : {}", key, describe(result));
+    }
+    if !unanswered.is_empty() {
+        println!("no answer for the keys {:?}", unanswered);
+    }
+}
+
+/// Prints how many keys of the batches `ids` were written, then the keys which were not.
+fn report_load(answers: &Receiver<Value>, ids: &[i64], keys: &[i64]) {
+    let (results, unanswered): (BTreeMap<i64, Value>, Vec<i64>) = gather(answers, ids, keys);
+    let refused: Vec<i64> = results
+        .iter()
+        .filter(|(_, r)| r["error"].is_string())
+        .map(|(&k, _)| k)
+        .collect();
+    println!("{} keys written", results.len() - refused.len());
+    if !refused.is_empty() {
+        println!("refused keys {:?}", refused);
+    }
+    if !unanswered.is_empty() {
+        println!("no answer for the keys {:?}", unanswered);
+    }
+}
+
+/// Keys of the entries of a `MultiPut`.
+fn entry_keys(entries: &[Value]) -> Vec<i64> {
+    entries.iter().filter_map(|e| e["key"].as_i64()).collect()
+}
+
+/// Route of a get or a put, the owner tells `addr_l` when its cached location is stale.
+fn request_route(traced: bool, mode: Mode, addr_l: &Address) -> Route {
+    match mode {
+        Mode::Cached => Route::new(traced).cached_by(addr_l.clone()),
+        _ => Route::new(traced),
+    }
+}
+
+/// Sends a request a node gave back again, to the node it redirects it to.
+fn retry_redirected(args: &Value) {
+    if let (Some(next), true) = (
+        get_addr_from_json(args, "next"),
+        args["request"].is_object(),
+    ) {
+        println!("redirected to {:?}", next);
+        next.send_json(args["request"].clone());
+    }
+}
+
+/// Splits the optional consistency level ending a command from its other arguments.
+fn take_consistency<'a>(words: &'a [&'a str]) -> (&'a [&'a str], Consistency) {
+    match words.split_last() {
+        Some((last, rest)) => match Consistency::parse(last) {
+            Some(c) => (rest, c),
+            None => (words, Consistency::One),
+        },
+        None => (words, Consistency::One),
+    }
+}
+
+/// Parses `<key> <value>` pairs into the entries of a `MultiPut`.
+fn parse_entries(words: &[&str]) -> Option<Vec<Value>> {
+    if !words.len().is_multiple_of(2) {
+        return None;
+    }
+    words
+        .chunks(2)
+        .map(
+            |pair| match (pair[0].parse::<i64>(), pair[1].parse::<f64>()) {
+                (Ok(key), Ok(value)) => Some(json!({"key" : key, "value" : value})),
+                _ => None,
+            },
+        )
+        .collect()
+}
 
 fn main() {
     if let Some(param) = get_args() {
@@ -24,39 +242,79 @@ fn main() {
                 port_d,
             } => {
                 let (tx, rx) = mpsc::channel();
+                let (answers_tx, answers) = mpsc::channel::<Value>();
                 let addr_d: Address = Address::new(ip_d, port_d, -1);
                 let addr_l: Address = Address::new(ip, port, -1);
+                let cache: Arc<Mutex<LocationCache>> =
+                    Arc::new(Mutex::new(LocationCache::new(LOCATION_CACHE_SIZE)));
+                let stale: Arc<Mutex<LocationCache>> = cache.clone();
                 let t: Option<JoinHandle<()>> = match TcpListener::bind(format!("{}:{}", ip, port))
                 {
-                    Ok(sock) => Some(std::thread::spawn(move || loop {
-                        match rx.try_recv() {
-                            Err(TryRecvError::Empty) => {
-                                let stream = sock.accept();
-                                if let Ok((mut s, _d)) = stream {
-                                    let mut buffer: [u8; 512] = [0; 512];
-                                    let u: usize = match s.read(&mut buffer) {
-                                        Ok(s) => s,
-                                        Err(_) => 0,
-                                    };
-                                    let j: Value = match serde_json::from_slice(&buffer[0..u]) {
-                                        Ok(v) => v,
-                                        Err(_) => serde_json::json!({}),
-                                    };
-                                    println!("{:?}", j);
+                    Ok(sock) => Some(std::thread::spawn(move || {
+                        let mut export: Option<File> = None;
+                        let mut stop: bool = false;
+                        while !stop {
+                            let stream = sock.accept();
+                            // the prompt sends its events before the messages they relate to
+                            for event in rx.try_iter() {
+                                match event {
+                                    Event::Stop => stop = true,
+                                    Event::Export(f) => export = Some(f),
+                                }
+                            }
+                            if let Ok((s, _d)) = stream {
+                                let j: Value = match read_parse(s) {
+                                    Some(v) => v,
+                                    None => serde_json::json!({}),
+                                };
+                                match (j["cmd"].as_str(), export.as_mut()) {
+                                    (Some("dump_page"), Some(f)) => export_page(f, &j["args"]),
+                                    (Some("multi_answer"), _) => {
+                                        let _ = answers_tx.send(j["args"].to_owned());
+                                    }
+                                    (Some("moved"), _) => {
+                                        if let Some(key) = j["args"]["key"].as_i64() {
+                                            println!("the owner of key {} changed", key);
+                                            let mut cache: MutexGuard<LocationCache> =
+                                                stale.lock().unwrap_or_else(|e| e.into_inner());
+                                            cache.invalidate(key);
+                                            if let (Some(next), Some(previous)) = (
+                                                get_addr_from_json(&j["args"], "next"),
+                                                j["args"]["previous"].as_i64(),
+                                            ) {
+                                                cache.insert(previous, next);
+                                            }
+                                        }
+                                        retry_redirected(&j["args"]);
+                                    }
+                                    _ => println!("{:?}", j),
                                 }
                             }
-                            Ok(_) | Err(TryRecvError::Disconnected) => break,
-                        };
+                        }
                     })),
                     _ => None,
                 };
 
                 if let Some(t) = t {
-                    println!("there are three command :");
-                    println!("get <key>");
-                    println!("put <key> <value>");
+                    println!("there are the commands :");
+                    println!("get <key> [one|quorum|all]");
+                    println!("put <key> <value> [ttl in seconds] [one|quorum|all]");
+                    println!("incr <key> [delta] // add delta (default 1) to the value of key");
+                    println!("scan <start key> <end key>");
+                    println!("mget <key> [<key> ...]");
+                    println!("mput <key> <value> [<key> <value> ...]");
+                    println!("load <file> // one \"<key> <value>\" per line, sent as one batch");
+                    println!("export <file> // save every entry of the ring in a file");
+                    println!("import <file> // put back the entries of an export");
+                    println!("lookup <key> // ask the nodes one by one who owns key");
+                    println!("locate <key> // let the ring find who owns key");
+                    println!("mode <recursive|iterative|cached> // how get and put find the owner");
+                    println!("trace <on|off> // show the nodes get, put and incr went through");
+                    println!("rebalance [keys|bytes|requests] // move a light node next to the most loaded one");
                     println!("exit // to stop the client");
                     println!("stop_all // to stop the client and all the servers");
+                    let mut mode: Mode = Mode::Recursive;
+                    let mut traced: bool = false;
                     loop {
                         let mut rng = rand::thread_rng();
                         let mut s: String = String::new();
@@ -73,11 +331,11 @@ fn main() {
                         println!("You typed: {}", s);
 
                         if s.starts_with("exit") {
-                            tx.send(()).unwrap();
+                            tx.send(Event::Stop).unwrap();
                             addr_l.send_message(Exit());
                             break;
                         } else if s.starts_with("stop_all") {
-                            tx.send(()).unwrap();
+                            tx.send(Event::Stop).unwrap();
                             addr_l.send_message(Exit());
                             addr_d.send_message(Exit());
                             break;
@@ -86,32 +344,281 @@ fn main() {
                             if !cmd.is_empty() {
                                 match cmd[0] {
                                     "get" => {
-                                        if cmd.len() == 2 {
-                                            if let Ok(key) = cmd[1].parse::<i64>() {
-                                                addr_d.send_message(Get(addr_l.clone(), key));
+                                        let (args, consistency) = take_consistency(&cmd[1..]);
+                                        if args.len() == 1 {
+                                            if let Ok(key) = args[0].parse::<i64>() {
+                                                if let Some(d) =
+                                                    destination(&addr_d, ip, key, mode, &cache)
+                                                {
+                                                    d.send_message(Get(
+                                                        addr_l.clone(),
+                                                        key,
+                                                        consistency,
+                                                        request_route(traced, mode, &addr_l),
+                                                    ));
+                                                }
                                             } else {
                                                 println!("key is not an int");
                                             }
                                         } else {
-                                            println!("usage : get <key>")
+                                            println!("usage : get <key> [one|quorum|all]")
                                         }
                                     }
                                     "put" => {
-                                        if cmd.len() == 3 {
-                                            match (cmd[1].parse::<i64>(), cmd[2].parse::<f64>()) {
-                                                (Ok(key), Ok(value)) => {
-                                                    let ack: i64 = rng.gen::<i64>();
-                                                    addr_d.send_message(Put(
+                                        let (args, consistency) = take_consistency(&cmd[1..]);
+                                        if args.len() == 2 || args.len() == 3 {
+                                            let ttl: Result<Option<i64>, _> = match args.get(2) {
+                                                Some(t) => t.parse::<i64>().map(Some),
+                                                None => Ok(None),
+                                            };
+                                            if let Ok(Some(t)) =
+                                                ttl.as_ref().map(|t| t.filter(|&t| t <= 0))
+                                            {
+                                                println!(
+                                                    "the ttl {} is not a positive number of seconds",
+                                                    t
+                                                );
+                                            } else if let (Ok(key), Ok(value), Ok(ttl)) = (
+                                                args[0].parse::<i64>(),
+                                                args[1].parse::<f64>(),
+                                                ttl,
+                                            ) {
+                                                let ack: i64 = rng.gen::<i64>();
+                                                if let Some(d) =
+                                                    destination(&addr_d, ip, key, mode, &cache)
+                                                {
+                                                    d.send_message(Put(
                                                         addr_l.clone(),
                                                         key,
                                                         value,
                                                         ack,
+                                                        ttl,
+                                                        consistency,
+                                                        request_route(traced, mode, &addr_l),
+                                                    ));
+                                                }
+                                            }
+                                        } else {
+                                            println!(
+                                                "usage : put <key> <value> [ttl] [one|quorum|all]"
+                                            )
+                                        }
+                                    }
+                                    "incr" => {
+                                        if cmd.len() == 2 || cmd.len() == 3 {
+                                            let delta: Result<f64, _> = match cmd.get(2) {
+                                                Some(d) => d.parse::<f64>(),
+                                                None => Ok(1.0),
+                                            };
+                                            if let (Ok(key), Ok(delta)) =
+                                                (cmd[1].parse::<i64>(), delta)
+                                            {
+                                                addr_d.send_message(Incr(
+                                                    addr_l.clone(),
+                                                    key,
+                                                    delta,
+                                                    Route::new(traced),
+                                                ));
+                                            } else {
+                                                println!("usage : incr <key> [delta]");
+                                            }
+                                        } else {
+                                            println!("usage : incr <key> [delta]")
+                                        }
+                                    }
+                                    "scan" => {
+                                        if cmd.len() == 3 {
+                                            if let (Ok(start), Ok(end)) =
+                                                (cmd[1].parse::<i64>(), cmd[2].parse::<i64>())
+                                            {
+                                                addr_d.send_message(Scan(
+                                                    addr_l.clone(),
+                                                    start,
+                                                    end,
+                                                    None,
+                                                    Route::new(false),
+                                                ));
+                                            } else {
+                                                println!("keys are not ints");
+                                            }
+                                        } else {
+                                            println!("usage : scan <start key> <end key>")
+                                        }
+                                    }
+                                    "mget" => {
+                                        let keys: Result<Vec<i64>, _> =
+                                            cmd[1..].iter().map(|k| k.parse::<i64>()).collect();
+                                        match keys {
+                                            Ok(keys) if !keys.is_empty() => {
+                                                let id: i64 = rng.gen::<i64>();
+                                                addr_d.send_message(MultiGet(
+                                                    addr_l.clone(),
+                                                    json!(keys),
+                                                    id,
+                                                    Route::new(false),
+                                                ));
+                                                report_batch(&answers, id, &keys);
+                                            }
+                                            _ => println!("usage : mget <key> [<key> ...]"),
+                                        }
+                                    }
+                                    "mput" => match parse_entries(&cmd[1..]) {
+                                        Some(entries) if !entries.is_empty() => {
+                                            let id: i64 = rng.gen::<i64>();
+                                            let keys: Vec<i64> = entry_keys(&entries);
+                                            addr_d.send_message(MultiPut(
+                                                addr_l.clone(),
+                                                Value::Array(entries),
+                                                id,
+                                                Route::new(false),
+                                            ));
+                                            report_batch(&answers, id, &keys);
+                                        }
+                                        _ => println!(
+                                            "usage : mput <key> <value> [<key> <value> ...]"
+                                        ),
+                                    },
+                                    "load" => {
+                                        if cmd.len() == 2 {
+                                            match read_to_string(cmd[1]) {
+                                                Ok(content) => {
+                                                    let words: Vec<&str> =
+                                                        content.split_whitespace().collect();
+                                                    if let Some(entries) = parse_entries(&words) {
+                                                        println!("loading {} keys", entries.len());
+                                                        let id: i64 = rng.gen::<i64>();
+                                                        let keys: Vec<i64> = entry_keys(&entries);
+                                                        addr_d.send_message(MultiPut(
+                                                            addr_l.clone(),
+                                                            Value::Array(entries),
+                                                            id,
+                                                            Route::new(false),
+                                                        ));
+                                                        report_load(&answers, &[id], &keys);
+                                                    } else {
+                                                        println!("the file must contain <key> <value> pairs");
+                                                    }
+                                                }
+                                                Err(e) => println!("can't read {} : {}", cmd[1], e),
+                                            }
+                                        } else {
+                                            println!("usage : load <file>")
+                                        }
+                                    }
+                                    "export" => {
+                                        if cmd.len() == 2 {
+                                            match File::create(cmd[1]) {
+                                                Ok(f) => {
+                                                    tx.send(Event::Export(f)).unwrap();
+                                                    addr_d.send_message(Dump(
+                                                        addr_l.clone(),
+                                                        None,
+                                                        Route::new(false),
                                                     ));
                                                 }
-                                                (_, _) => {}
+                                                Err(e) => {
+                                                    println!("can't create {} : {}", cmd[1], e)
+                                                }
                                             }
                                         } else {
-                                            println!("usage : get <key>")
+                                            println!("usage : export <file>")
+                                        }
+                                    }
+                                    "import" => {
+                                        if cmd.len() == 2 {
+                                            match read_to_string(cmd[1])
+                                                .map(|c| import_entries(&c, now_millis()))
+                                            {
+                                                Ok(Some(entries)) => {
+                                                    println!("importing {} entries", entries.len());
+                                                    let mut ids: Vec<i64> = Vec::new();
+                                                    for batch in entries.chunks(IMPORT_BATCH) {
+                                                        let id: i64 = rng.gen::<i64>();
+                                                        addr_d.send_message(MultiPut(
+                                                            addr_l.clone(),
+                                                            Value::Array(batch.to_vec()),
+                                                            id,
+                                                            Route::new(false),
+                                                        ));
+                                                        ids.push(id);
+                                                    }
+                                                    report_load(
+                                                        &answers,
+                                                        &ids,
+                                                        &entry_keys(&entries),
+                                                    );
+                                                }
+                                                Ok(None) => println!("{} is not an export", cmd[1]),
+                                                Err(e) => println!("can't read {} : {}", cmd[1], e),
+                                            }
+                                        } else {
+                                            println!("usage : import <file>")
+                                        }
+                                    }
+                                    "lookup" => match cmd.get(1).map(|k| k.parse::<i64>()) {
+                                        Some(Ok(key)) if cmd.len() == 2 => {
+                                            match lookup_iterative(&addr_d, ip, key, HOP_TIMEOUT) {
+                                                Some(l) => {
+                                                    let path: Vec<i64> = l
+                                                        .get_path()
+                                                        .iter()
+                                                        .map(|a| a.get_id())
+                                                        .collect();
+                                                    println!(
+                                                        "key {} is owned by {:?}, asked {:?}",
+                                                        key,
+                                                        l.get_owner(),
+                                                        path
+                                                    );
+                                                }
+                                                None => println!("no owner found for key {}", key),
+                                            }
+                                        }
+                                        _ => println!("usage : lookup <key>"),
+                                    },
+                                    "locate" => match cmd.get(1).map(|k| k.parse::<i64>()) {
+                                        Some(Ok(key)) if cmd.len() == 2 => {
+                                            let found: Option<Location> =
+                                                locate(&addr_d, ip, key, HOP_TIMEOUT);
+                                            match found {
+                                                Some(l) => println!(
+                                                    "key {} is owned by {:?}, found in {} hops through {:?}",
+                                                    key,
+                                                    l.get_owner(),
+                                                    l.get_hops(),
+                                                    l.get_trace()
+                                                ),
+                                                None => println!("no owner found for key {}", key),
+                                            }
+                                        }
+                                        _ => println!("usage : locate <key>"),
+                                    },
+                                    "mode" => match cmd.get(1) {
+                                        Some(&"recursive") => mode = Mode::Recursive,
+                                        Some(&"iterative") => mode = Mode::Iterative,
+                                        Some(&"cached") => mode = Mode::Cached,
+                                        _ => {
+                                            println!("usage : mode <recursive|iterative|cached>")
+                                        }
+                                    },
+                                    "trace" => match cmd.get(1) {
+                                        Some(&"on") => traced = true,
+                                        Some(&"off") => traced = false,
+                                        _ => println!("usage : trace <on|off>"),
+                                    },
+                                    "rebalance" => {
+                                        let metric: &str = cmd.get(1).copied().unwrap_or("keys");
+                                        if cmd.len() <= 2
+                                            && ["keys", "bytes", "requests"].contains(&metric)
+                                        {
+                                            addr_d.send_message(Rebalance(
+                                                addr_l.clone(),
+                                                metric.to_string(),
+                                                json!([]),
+                                                Route::new(false),
+                                            ));
+                                        } else {
+                                            println!("usage : rebalance [keys|bytes|requests]")
                                         }
                                     }
                                     _ => println!("command not found"),
@@ -1,32 +1,138 @@
-mod app;
-mod chord;
-use app::server::parameter::{get_args, Param};
-use chord::address::Address;
-use chord::message::Message::Hello;
-use chord::node::{listen, Node};
+use copper::app::server::parameter::{get_args, Options, Param};
+use copper::chord::address::Address;
+use copper::chord::node::{listen_virtual, Node, MAX_NODE};
+use copper::chord::ring::RingId;
+use copper::chord::state::StateFile;
+#[cfg(feature = "sled")]
+use copper::chord::storage::SledStorage;
+use copper::chord::storage::{LogStorage, MemoryStorage, SharedStorage, Storage};
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
 use std::thread::JoinHandle;
 
+/// Gives the node the state file kept next to its data file, returns whether the node found
+/// a saved state and has to rejoin the ring through its saved successor. Virtual nodes each
+/// have their own file, named after their id.
+fn recover_state(n: &mut Node<Box<dyn Storage>>, data_file: &Option<PathBuf>, vnodes: i64) -> bool {
+    match data_file {
+        Some(path) if vnodes > 1 => n.recover(StateFile::new(
+            path.with_extension(format!("{}.state", n.get_addr().get_id())),
+        )),
+        Some(path) => n.recover(StateFile::new(path.with_extension("state"))),
+        None => false,
+    }
+}
+
+/// Ring positions of the `count` virtual nodes of a server, spread evenly from `id`.
+fn virtual_ids(id: i64, count: i64) -> Vec<i64> {
+    let first: RingId = RingId::new(id);
+    let mut ids: Vec<i64> = Vec::new();
+    for k in 0..count.min(MAX_NODE) {
+        let vid: i64 = (first + k * MAX_NODE / count).get();
+        if !ids.contains(&vid) {
+            ids.push(vid);
+        }
+    }
+    ids
+}
+
+/// Starts the virtual nodes of the server over `data`. Each one joins the ring through its
+/// saved successor when it recovered a state, else through `destination`, else through the
+/// first virtual node.
+fn start_nodes(
+    ip: Ipv4Addr,
+    port: i64,
+    id: i64,
+    data: Box<dyn Storage>,
+    data_file: &Option<PathBuf>,
+    options: &Options,
+    destination: Option<Address>,
+) -> Option<JoinHandle<()>> {
+    let ids: Vec<i64> = virtual_ids(id, options.vnodes);
+    let storages: Vec<Box<dyn Storage>> = if ids.len() > 1 {
+        let shared: SharedStorage = SharedStorage::new(data);
+        ids.iter()
+            .map(|_| Box::new(shared.clone()) as Box<dyn Storage>)
+            .collect()
+    } else {
+        vec![data]
+    };
+    let mut nodes: Vec<Node<Box<dyn Storage>>> = Vec::new();
+    for (vid, data) in ids.into_iter().zip(storages) {
+        let mut n: Node<Box<dyn Storage>> = Node::new(ip, port, vid, data);
+        n.set_replicas(options.replicas);
+        n.set_proximity(options.proximity);
+        n.set_redirect(options.redirect);
+        let addr_local: Address = n.get_addr();
+        let entry: Option<Address> = if recover_state(&mut n, data_file, options.vnodes) {
+            Some(n.get_successor())
+        } else if destination.is_some() {
+            destination.clone()
+        } else {
+            nodes.first().map(|f| f.get_addr())
+        };
+        if let Some(entry) = entry.filter(|e| *e != addr_local) {
+            n.join(entry);
+        }
+        nodes.push(n);
+    }
+    listen_virtual(nodes)
+}
+
+/// A data file named `*.sled` is opened as a sled database when the `sled` feature is
+/// enabled, any other one as an append-only log.
+fn open_storage(data_file: Option<PathBuf>) -> Option<Box<dyn Storage>> {
+    match data_file {
+        #[cfg(feature = "sled")]
+        Some(path) if path.extension().is_some_and(|e| e == "sled") => {
+            match SledStorage::open(path.clone()) {
+                Ok(s) => Some(Box::new(s)),
+                Err(e) => {
+                    println!("can't open the database {:?} : {}", path, e);
+                    None
+                }
+            }
+        }
+        Some(path) => match LogStorage::open(path.clone()) {
+            Ok(s) => Some(Box::new(s)),
+            Err(e) => {
+                println!("can't open the data file {:?} : {}", path, e);
+                None
+            }
+        },
+        None => Some(Box::new(MemoryStorage::new())),
+    }
+}
+
 // V3
 fn main() {
-    if let Some(param) = get_args() {
+    if let Some((param, options)) = get_args() {
         let t: Option<JoinHandle<()>> = match param {
-            Param::Short { ip, port, id } => {
-                let n: Node = Node::new(ip, port, id);
-                listen(n)
-            }
+            Param::Short {
+                ip,
+                port,
+                id,
+                data_file,
+            } => open_storage(data_file.clone())
+                .and_then(|data| start_nodes(ip, port, id, data, &data_file, &options, None)),
             Param::Long {
                 ip_local,
                 port_local,
                 id_local,
                 ip_destination,
                 port_destination,
-            } => {
-                let n: Node = Node::new(ip_local, port_local, id_local);
-                let addr_local: Address = n.get_addr();
-                let t: Option<JoinHandle<()>> = listen(n);
-                Address::new(ip_destination, port_destination, 0).send_message(Hello(addr_local));
-                t
-            }
+                data_file,
+            } => open_storage(data_file.clone()).and_then(|data| {
+                start_nodes(
+                    ip_local,
+                    port_local,
+                    id_local,
+                    data,
+                    &data_file,
+                    &options,
+                    Some(Address::new(ip_destination, port_destination, 0)),
+                )
+            }),
         };
         if let Some(t) = t {
             let res = t.join();
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{receive, reply_listener, start, stop, wait_for_ring, Running};
+use copper::chord::address::Address;
+use copper::chord::message::Message::{MultiGet, MultiPut};
+use copper::chord::route::Route;
+use serde_json::{json, Map, Value};
+use std::net::TcpListener;
+
+/// The per-key results of the batch `id`, gathered from the answers of every owner until each
+/// of `keys` has one.
+fn gather(sock: &TcpListener, id: i64, keys: &[i64]) -> Map<String, Value> {
+    let mut results: Map<String, Value> = Map::new();
+    while !keys.iter().all(|k| results.contains_key(&k.to_string())) {
+        let answer: Value = receive(sock, "multi_answer", "id", id)
+            .unwrap_or_else(|| panic!("batch {} answered for {:?} only", id, results.keys()));
+        results.extend(answer["results"].as_object().unwrap().clone());
+    }
+    results
+}
+
+#[test]
+fn a_batch_spread_over_several_owners_is_answered_key_by_key() {
+    let first: Running = start(0, None);
+    let second: Running = start(10, Some(&first.addr));
+    let third: Running = start(20, Some(&first.addr));
+    let nodes: Vec<Running> = vec![first, second, third];
+    wait_for_ring(&nodes);
+    // 3 is owned by 10, 13 and 14 by 20, 25 by 0
+    let entries: Value = json!([
+        {"key" : 3, "value" : 1.5, "ttl" : null},
+        {"key" : 13, "value" : 2.5, "ttl" : 60},
+        {"key" : 14, "value" : 9.0, "ttl" : 0},
+        {"key" : 25, "value" : 3.5, "ttl" : null},
+    ]);
+    let (sock, local): (TcpListener, Address) = reply_listener();
+    nodes[0]
+        .addr
+        .send_message(MultiPut(local, entries, 1, Route::new(false)));
+    let written: Map<String, Value> = gather(&sock, 1, &[3, 13, 14, 25]);
+    for key in ["3", "13", "25"] {
+        assert!(written[key]["version"].is_object(), "{} not written", key);
+    }
+    assert!(written["14"]["error"].is_string());
+
+    let (sock, local): (TcpListener, Address) = reply_listener();
+    nodes[1].addr.send_message(MultiGet(
+        local,
+        json!([3, 13, 14, 25]),
+        2,
+        Route::new(false),
+    ));
+    let read: Map<String, Value> = gather(&sock, 2, &[3, 13, 14, 25]);
+    for (key, value) in [("3", 1.5), ("13", 2.5), ("25", 3.5)] {
+        assert_eq!(read[key]["value_exists"], true);
+        assert_eq!(read[key]["value"].as_f64(), Some(value));
+        assert_eq!(read[key]["version"], written[key]["version"]);
+    }
+    assert_eq!(read["14"]["value_exists"], false);
+    stop(nodes);
+}
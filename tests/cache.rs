@@ -0,0 +1,82 @@
+use copper::chord::address::Address;
+use copper::chord::cache::LocationCache;
+use std::net::Ipv4Addr;
+
+fn owner(id: i64) -> Address {
+    Address::new(Ipv4Addr::LOCALHOST, 16000 + id, id)
+}
+
+fn owner_id(cache: &mut LocationCache, key: i64) -> Option<i64> {
+    cache.get(key).map(|a| a.get_id())
+}
+
+#[test]
+fn a_range_holds_the_keys_after_the_previous_node_up_to_its_owner() {
+    let mut cache: LocationCache = LocationCache::new(8);
+    cache.insert(10, owner(20));
+    assert_eq!(owner_id(&mut cache, 11), Some(20));
+    assert_eq!(owner_id(&mut cache, 20), Some(20));
+    assert_eq!(owner_id(&mut cache, 10), None);
+    assert_eq!(owner_id(&mut cache, 21), None);
+    // keys are taken modulo the ring
+    assert_eq!(owner_id(&mut cache, 44), Some(20));
+
+    // a range may wrap around the end of the ring
+    cache.insert(28, owner(3));
+    for key in [29, 31, 0, 3] {
+        assert_eq!(owner_id(&mut cache, key), Some(3));
+    }
+    assert_eq!(owner_id(&mut cache, 28), None);
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn the_least_recently_used_range_goes_first() {
+    let mut cache: LocationCache = LocationCache::new(2);
+    cache.insert(0, owner(5));
+    cache.insert(5, owner(10));
+    // using the first range makes the second one the oldest
+    assert_eq!(owner_id(&mut cache, 3), Some(5));
+    cache.insert(10, owner(15));
+    assert_eq!(cache.len(), 2);
+    assert_eq!(owner_id(&mut cache, 7), None);
+    assert_eq!(owner_id(&mut cache, 3), Some(5));
+    assert_eq!(owner_id(&mut cache, 12), Some(15));
+
+    let mut disabled: LocationCache = LocationCache::new(0);
+    disabled.insert(0, owner(5));
+    assert!(disabled.is_empty());
+}
+
+#[test]
+fn a_new_range_replaces_the_ranges_it_overlaps() {
+    let mut cache: LocationCache = LocationCache::new(8);
+    cache.insert(10, owner(20));
+    cache.insert(22, owner(26));
+    // a node joined at 15: the keys up to it are not 20's anymore
+    cache.insert(10, owner(15));
+    assert_eq!(owner_id(&mut cache, 12), Some(15));
+    assert_eq!(owner_id(&mut cache, 18), None);
+    // a range holding the owner of a cached one, which left
+    cache.insert(21, owner(30));
+    assert_eq!(owner_id(&mut cache, 24), Some(30));
+    // a range ending inside a cached one
+    cache.insert(8, owner(12));
+    assert_eq!(owner_id(&mut cache, 14), None);
+    assert_eq!(owner_id(&mut cache, 9), Some(12));
+    assert_eq!(owner_id(&mut cache, 28), Some(30));
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn invalidate_only_forgets_the_range_of_the_key() {
+    let mut cache: LocationCache = LocationCache::new(8);
+    cache.insert(0, owner(10));
+    cache.insert(10, owner(20));
+    cache.invalidate(25);
+    assert_eq!(cache.len(), 2);
+    cache.invalidate(42);
+    assert_eq!(owner_id(&mut cache, 5), None);
+    assert_eq!(owner_id(&mut cache, 15), Some(20));
+    assert_eq!(cache.len(), 1);
+}
@@ -0,0 +1,267 @@
+#![allow(dead_code)]
+
+use copper::chord::address::Address;
+use copper::chord::consistency::Consistency;
+use copper::chord::entry::Version;
+use copper::chord::lookup::{locate, Location};
+use copper::chord::message::Message;
+use copper::chord::message::Message::{Exit, Get, Put};
+use copper::chord::node::{key_position, listen, listen_virtual, read_parse, Node, MAX_NODE};
+use copper::chord::route::Route;
+use copper::chord::state::StateFile;
+use copper::chord::storage::{MemoryStorage, Storage};
+use serde_json::{json, Value};
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::Path;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long `ask` waits for a reply.
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a ring may take to settle once every node said `Hello`.
+pub const SETTLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A port nothing listens on, so that test binaries running at the same time don't collide.
+pub fn free_port() -> i64 {
+    let sock: TcpListener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    sock.local_addr().unwrap().port() as i64
+}
+
+/// A node of the test ring, stopped by `stop`.
+pub struct Running {
+    pub addr: Address,
+    /// the thread of its server, kept by the first of the virtual nodes sharing it
+    handle: Option<JoinHandle<()>>,
+}
+
+/// The node `id` over `data`, on a free port.
+pub fn new_node<S: Storage>(id: i64, data: S) -> Node<S> {
+    Node::new(Ipv4Addr::LOCALHOST, free_port(), id, data)
+}
+
+pub fn run<S: Storage + 'static>(n: Node<S>) -> Running {
+    let addr: Address = n.get_addr();
+    let handle: JoinHandle<()> = listen(n).expect("the node can't listen");
+    Running {
+        addr,
+        handle: Some(handle),
+    }
+}
+
+/// Runs `nodes` as the virtual nodes of one server.
+pub fn run_virtual<S: Storage + 'static>(nodes: Vec<Node<S>>) -> Vec<Running> {
+    let addrs: Vec<Address> = nodes.iter().map(|n| n.get_addr()).collect();
+    let mut handle: Option<JoinHandle<()>> =
+        Some(listen_virtual(nodes).expect("the server can't listen"));
+    addrs
+        .into_iter()
+        .map(|addr| Running {
+            addr,
+            handle: handle.take(),
+        })
+        .collect()
+}
+
+/// Starts the node `id` with the settings `configure` gives it, joining the ring through
+/// `entry` when there is one.
+pub fn start_with(
+    id: i64,
+    entry: Option<&Address>,
+    configure: impl FnOnce(&mut Node<MemoryStorage>),
+) -> Running {
+    let mut n: Node<MemoryStorage> = new_node(id, MemoryStorage::new());
+    configure(&mut n);
+    if let Some(entry) = entry {
+        n.join(entry.clone());
+    }
+    run(n)
+}
+
+pub fn start(id: i64, entry: Option<&Address>) -> Running {
+    start_with(id, entry, |_| {})
+}
+
+/// Makes `n` recover the state, saved at `path`, of a ring where `other` is its only other
+/// node. `other` may then be a listener of the test standing for that node.
+pub fn recover_beside<S: Storage>(n: &mut Node<S>, other: &Address, path: &Path) {
+    let id: i64 = n.get_addr().get_id();
+    let fingers: Vec<Value> = (0..5)
+        .map(|i| json!({"key" : key_position(id + (1 << i)), "address" : other.to_json()}))
+        .collect();
+    let state: Value = json!({
+        "address" : n.get_addr().to_json(),
+        "previous" : other.to_json(),
+        "association" : fingers,
+        "clock" : 0,
+    });
+    std::fs::write(path, state.to_string()).unwrap();
+    assert!(n.recover(StateFile::new(path.to_path_buf())));
+}
+
+/// Sends an `Exit` to every node and waits for all of them to stop listening.
+pub fn stop(nodes: Vec<Running>) {
+    for n in nodes.iter() {
+        n.addr.send_message(Exit());
+    }
+    for handle in nodes.into_iter().filter_map(|n| n.handle) {
+        handle.join().unwrap();
+    }
+}
+
+/// Opens a listener for replies, with the address to give the nodes.
+pub fn reply_listener() -> (TcpListener, Address) {
+    let sock: TcpListener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    sock.set_nonblocking(true).unwrap();
+    let port: i64 = sock.local_addr().unwrap().port() as i64;
+    (sock, Address::new(Ipv4Addr::LOCALHOST, port, -1))
+}
+
+/// Waits on `sock` for the message `cmd` whose `field` is `value`, returns its arguments.
+pub fn receive(sock: &TcpListener, cmd: &str, field: &str, value: i64) -> Option<Value> {
+    receive_where(sock, cmd, |args| args[field].as_i64() == Some(value))
+}
+
+/// Waits on `sock` for the message `cmd` whose arguments pass `wanted`, returns them.
+pub fn receive_where(
+    sock: &TcpListener,
+    cmd: &str,
+    wanted: impl Fn(&Value) -> bool,
+) -> Option<Value> {
+    let deadline: Instant = Instant::now() + REPLY_TIMEOUT;
+    loop {
+        let v: Value = next_message(sock, deadline)?;
+        if v["cmd"] == cmd && wanted(&v["args"]) {
+            return Some(v["args"].to_owned());
+        }
+    }
+}
+
+/// Waits on `sock` for the next message, whatever it is, returns it whole.
+pub fn receive_next(sock: &TcpListener) -> Option<Value> {
+    next_message(sock, Instant::now() + REPLY_TIMEOUT)
+}
+
+fn next_message(sock: &TcpListener, deadline: Instant) -> Option<Value> {
+    loop {
+        match sock.accept() {
+            Ok((s, _)) => {
+                if s.set_nonblocking(false).is_err() {
+                    continue;
+                }
+                if let Some(v) = read_parse(s) {
+                    return Some(v);
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return None;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Err(_) => return None,
+        }
+    }
+}
+
+/// Sends the message `build` makes for a reply address to `to` and waits for the reply `cmd`
+/// whose `field` is `value`.
+pub fn ask(
+    to: &Address,
+    build: impl Fn(Address) -> Message,
+    cmd: &str,
+    field: &str,
+    value: i64,
+) -> Option<Value> {
+    let (sock, local): (TcpListener, Address) = reply_listener();
+    to.send_message(build(local))?;
+    receive(&sock, cmd, field, value)
+}
+
+/// Writes `value` under `key` through `node` as the write `id`, returns its ack. The route is
+/// traced, the ack tells the nodes it went through.
+pub fn put(node: &Address, key: i64, value: f64, id: i64) -> Value {
+    ask(
+        node,
+        |local| {
+            Put(
+                local,
+                key,
+                value,
+                id,
+                None,
+                Consistency::One,
+                Route::new(true),
+            )
+        },
+        "ack",
+        "id",
+        id,
+    )
+    .unwrap_or_else(|| panic!("put {} of {} not acked", id, key))
+}
+
+/// The value and version of `key` read through `node`, `None` when it has none.
+pub fn get(node: &Address, key: i64) -> Option<(f64, Version)> {
+    get_with(node, key, Consistency::One)
+}
+
+/// The value and version of `key` read through `node` at the level `consistency`.
+pub fn get_with(node: &Address, key: i64, consistency: Consistency) -> Option<(f64, Version)> {
+    let answer: Value = ask(
+        node,
+        |local| Get(local, key, consistency, Route::new(false)),
+        "answer",
+        "key",
+        key,
+    )
+    .unwrap_or_else(|| panic!("get of {} not answered", key));
+    if answer["value_exists"].as_bool() != Some(true) {
+        return None;
+    }
+    Some((
+        answer["value"].as_f64().unwrap(),
+        Version::from_json(&answer["version"]).unwrap(),
+    ))
+}
+
+/// First node of `ids`, sorted, at or after the ring position `position`.
+fn successor(ids: &[i64], position: i64) -> i64 {
+    match ids.iter().find(|&&id| id >= position) {
+        Some(&id) => id,
+        None => ids[0],
+    }
+}
+
+fn previous(ids: &[i64], id: i64) -> i64 {
+    match ids.iter().rev().find(|&&other| other < id) {
+        Some(&other) => other,
+        None => *ids.last().unwrap(),
+    }
+}
+
+/// Whether every node finds the true owner of every key, and that owner knows its true
+/// previous node.
+pub fn is_consistent(nodes: &[Running]) -> bool {
+    let mut ids: Vec<i64> = nodes.iter().map(|n| n.addr.get_id()).collect();
+    ids.sort_unstable();
+    nodes.iter().all(|from| {
+        (0..MAX_NODE).all(|key| {
+            let location: Option<Location> =
+                locate(&from.addr, Ipv4Addr::LOCALHOST, key, Duration::from_secs(2));
+            let owner: i64 = successor(&ids, key_position(key));
+            location.is_some_and(|l| {
+                l.get_owner().get_id() == owner && l.get_previous() == previous(&ids, owner)
+            })
+        })
+    })
+}
+
+/// Waits for the ring of `nodes` to be consistent.
+pub fn wait_for_ring(nodes: &[Running]) {
+    let deadline: Instant = Instant::now() + SETTLE_TIMEOUT;
+    while !is_consistent(nodes) {
+        assert!(Instant::now() < deadline, "the ring did not settle");
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use common::{
+    ask, get, get_with, receive, reply_listener, start_with, stop, wait_for_ring, Running,
+};
+use copper::chord::address::Address;
+use copper::chord::consistency::Consistency;
+use copper::chord::entry::{data_to_json, Entry, Version};
+use copper::chord::message::Message::{Get, Put, ReplicaRead, Replicate, Tick, Transfer};
+use copper::chord::route::Route;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::time::Duration;
+
+/// How long the owner waits for the copies of a key, as set in the node.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn data(key: i64, entry: Entry) -> Value {
+    let mut data: HashMap<i64, Entry> = HashMap::new();
+    data.insert(key, entry);
+    data_to_json(&data)
+}
+
+/// The copy of `key` the replica `node` keeps.
+fn replica_copy(node: &Address, key: i64) -> Version {
+    let value: Value = ask(
+        node,
+        |local| ReplicaRead(local, 99, key, 1),
+        "replica_value",
+        "request",
+        99,
+    )
+    .unwrap();
+    Version::from_json(&value["entry"]["version"]).unwrap()
+}
+
+#[test]
+fn the_level_sets_how_many_copies_answer() {
+    for copies in 1..=5 {
+        assert_eq!(Consistency::One.required(copies), 1);
+        assert_eq!(Consistency::All.required(copies), copies);
+    }
+    let quorums: Vec<i64> = (1..=5).map(|c| Consistency::Quorum.required(c)).collect();
+    assert_eq!(quorums, vec![1, 2, 2, 3, 3]);
+    assert_eq!(
+        Consistency::from_json(&json!("QUORUM")),
+        Consistency::Quorum
+    );
+    // older clients send no level
+    assert_eq!(Consistency::from_json(&Value::Null), Consistency::One);
+    assert_eq!(Consistency::parse("most"), None);
+}
+
+#[test]
+fn requests_missing_copies_fail_once_they_time_out() {
+    // one replica is wanted but the node is alone, no copy but its own ever answers
+    let node: Running = start_with(3, None, |n| n.set_replicas(1));
+    // the errors come in any order, each request gets its own listener
+    let (put_sock, put_local): (TcpListener, Address) = reply_listener();
+    let (get_sock, get_local): (TcpListener, Address) = reply_listener();
+    node.addr.send_message(Put(
+        put_local,
+        1,
+        1.0,
+        7,
+        None,
+        Consistency::All,
+        Route::new(false),
+    ));
+    node.addr
+        .send_message(Get(get_local, 1, Consistency::Quorum, Route::new(false)));
+    // a level of one is still answered at once
+    assert_eq!(get(&node.addr, 1).unwrap().0, 1.0);
+
+    std::thread::sleep(REQUEST_TIMEOUT);
+    node.addr.send_message(Tick());
+    let put: Value = receive(&put_sock, "error", "id", 7).expect("the put did not fail");
+    assert!(put["reason"].as_str().unwrap().contains("only 1 of the 2"));
+    // a failed read is reported under its key
+    let get: Value = receive(&get_sock, "error", "id", 1).expect("the get did not fail");
+    assert!(get["reason"].as_str().unwrap().contains("key 1"));
+    stop(vec![node]);
+}
+
+#[test]
+fn a_read_returns_the_newest_copy_and_repairs_the_others() {
+    let first: Running = start_with(10, None, |n| n.set_replicas(1));
+    let second: Running = start_with(20, Some(&first.addr), |n| n.set_replicas(1));
+    let nodes: Vec<Running> = vec![first, second];
+    wait_for_ring(&nodes);
+    let (owner, replica): (&Address, &Address) = (&nodes[0].addr, &nodes[1].addr);
+
+    // 5 is in (20, 10], the node 20 keeps its copy
+    let acked: Option<Value> = ask(
+        owner,
+        |local| Put(local, 5, 1.0, 1, None, Consistency::All, Route::new(false)),
+        "ack",
+        "id",
+        1,
+    );
+    assert!(acked.is_some());
+    let (_, written): (f64, Version) = get(owner, 5).unwrap();
+    assert_eq!(replica_copy(replica, 5), written);
+
+    // the replica got a newer write the owner missed
+    let newer: Version = Version::new(written.get_counter() + 50, 20);
+    replica.send_message(Replicate(
+        owner.clone(),
+        data(5, Entry::new(7.0, newer, None)),
+        1,
+        None,
+    ));
+    assert_eq!(get_with(owner, 5, Consistency::Quorum), Some((7.0, newer)));
+    assert_eq!(get(owner, 5), Some((7.0, newer)));
+
+    // now the owner got a write the replica missed
+    let newest: Version = Version::new(newer.get_counter() + 50, 10);
+    let acked: Option<Value> = ask(
+        owner,
+        |local| Transfer(local, data(5, Entry::new(9.0, newest, None)), 0),
+        "transfer_ack",
+        "seq",
+        0,
+    );
+    assert!(acked.is_some());
+    assert_eq!(replica_copy(replica, 5), newer);
+    assert_eq!(get_with(owner, 5, Consistency::Quorum), Some((9.0, newest)));
+    // the repair is sent once the read is answered, the next answer comes after it
+    get(owner, 5);
+    assert_eq!(replica_copy(replica, 5), newest);
+    stop(nodes);
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use common::{ask, get, put, start, stop, Running};
+use copper::chord::address::Address;
+use copper::chord::entry::{data_to_json, now_millis, Entry, Version};
+use copper::chord::message::Message::Transfer;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Hands `entries` to `node` the way a node leaving its keys does.
+fn transfer(node: &Address, seq: i64, entries: &[(i64, f64, Version)]) {
+    let data: HashMap<i64, Entry> = entries
+        .iter()
+        .map(|&(key, value, version)| (key, Entry::new(value, version, None)))
+        .collect();
+    let acked: Option<Value> = ask(
+        node,
+        |local| Transfer(local, data_to_json(&data), seq),
+        "transfer_ack",
+        "seq",
+        seq,
+    );
+    assert!(acked.is_some(), "transfer {} not acked", seq);
+}
+
+#[test]
+fn versions_order_by_counter_then_writer() {
+    assert!(Version::new(2, 0) > Version::new(1, 31));
+    assert!(Version::new(1, 5) > Version::new(1, 4));
+    assert_eq!(Version::new(3, 7), Version::new(3, 7));
+    let mut versions: Vec<Version> = vec![
+        Version::new(2, 1),
+        Version::new(1, 9),
+        Version::new(2, 0),
+        Version::new(0, 30),
+    ];
+    versions.sort();
+    assert_eq!(
+        versions,
+        vec![
+            Version::new(0, 30),
+            Version::new(1, 9),
+            Version::new(2, 0),
+            Version::new(2, 1)
+        ]
+    );
+    let v: Version = Version::new(12, 3);
+    assert_eq!(Version::from_json(&v.to_json()), Some(v));
+}
+
+#[test]
+fn only_a_newer_entry_replaces_the_stored_one() {
+    let old: Entry = Entry::new(1.0, Version::new(4, 2), None);
+    let new: Entry = Entry::new(2.0, Version::new(4, 3), None);
+    assert!(new.is_newer_than(&old));
+    assert!(!old.is_newer_than(&new));
+    assert!(!old.is_newer_than(&old));
+
+    let node: Running = start(7, None);
+    transfer(&node.addr, 0, &[(1, 1.0, Version::new(5, 2))]);
+    // an older copy does not overwrite the stored one
+    transfer(&node.addr, 1, &[(1, 9.0, Version::new(4, 20))]);
+    assert_eq!(get(&node.addr, 1), Some((1.0, Version::new(5, 2))));
+    // the same counter is decided by the writer
+    transfer(&node.addr, 2, &[(1, 3.0, Version::new(5, 3))]);
+    assert_eq!(get(&node.addr, 1), Some((3.0, Version::new(5, 3))));
+    transfer(&node.addr, 3, &[(1, 4.0, Version::new(8, 0))]);
+    assert_eq!(get(&node.addr, 1), Some((4.0, Version::new(8, 0))));
+    stop(vec![node]);
+}
+
+#[test]
+fn the_clock_moves_past_the_merged_writes() {
+    let node: Running = start(7, None);
+    // a write is never versioned behind the wall clock
+    let before: i64 = now_millis();
+    put(&node.addr, 1, 1.0, 41);
+    assert!(get(&node.addr, 1).unwrap().1.get_counter() >= before);
+
+    // a node whose clock ran ahead wrote it
+    let ahead: i64 = now_millis() + 3_600_000;
+    transfer(&node.addr, 0, &[(2, 1.0, Version::new(ahead, 3))]);
+    put(&node.addr, 2, 5.0, 42);
+    assert_eq!(get(&node.addr, 2), Some((5.0, Version::new(ahead + 1, 7))));
+    stop(vec![node]);
+}
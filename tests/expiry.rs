@@ -0,0 +1,80 @@
+mod common;
+
+use common::{ask, get, new_node, put, run, start, stop, Running};
+use copper::chord::address::Address;
+use copper::chord::consistency::Consistency;
+use copper::chord::entry::{expiry, Entry, Version};
+use copper::chord::message::Message::{Put, Tick};
+use copper::chord::route::Route;
+use copper::chord::storage::{MemoryStorage, SharedStorage, Storage};
+use serde_json::Value;
+use std::time::Duration;
+
+/// Writes `key` with the lifetime `ttl`, returns its ack or, for a ttl that is refused, its error.
+fn put_expiring(node: &Address, key: i64, ttl: i64, id: i64) -> Value {
+    // a refused write is answered by an error instead of an ack
+    let cmd: &str = if ttl <= 0 { "error" } else { "ack" };
+    ask(
+        node,
+        |local| {
+            Put(
+                local,
+                key,
+                1.5,
+                id,
+                Some(ttl),
+                Consistency::One,
+                Route::new(false),
+            )
+        },
+        cmd,
+        "id",
+        id,
+    )
+    .unwrap_or_else(|| panic!("put {} with the ttl {} not answered", id, ttl))
+}
+
+#[test]
+fn expiry_saturates_instead_of_overflowing() {
+    assert_eq!(expiry(1_000, 5), 6_000);
+    assert_eq!(expiry(1_000, i64::MAX), i64::MAX);
+    assert_eq!(expiry(i64::MAX - 10, 1), i64::MAX);
+    let e: Entry = Entry::new(1.0, Version::new(1, 0), Some(expiry(1_000, 2)));
+    assert!(!e.is_expired(2_999));
+    assert!(e.is_expired(3_000));
+    assert!(!Entry::new(1.0, Version::new(1, 0), None).is_expired(i64::MAX));
+}
+
+#[test]
+fn a_ttl_of_zero_or_less_is_refused() {
+    let node: Running = start(3, None);
+    for (id, ttl) in [(1, 0), (2, -5), (3, i64::MIN)] {
+        let refused: Value = put_expiring(&node.addr, 10 + id, ttl, id);
+        assert!(refused["reason"].as_str().unwrap().contains("ttl"));
+        assert_eq!(get(&node.addr, 10 + id), None);
+    }
+    // a huge ttl is kept, the entry just never expires
+    put_expiring(&node.addr, 20, i64::MAX, 4);
+    assert!(get(&node.addr, 20).is_some());
+    stop(vec![node]);
+}
+
+#[test]
+fn expired_entries_are_hidden_then_purged_on_tick() {
+    let data: SharedStorage = SharedStorage::new(Box::new(MemoryStorage::new()));
+    let node: Running = run(new_node(3, data.clone()));
+    put_expiring(&node.addr, 1, 1, 1);
+    put(&node.addr, 2, 1.5, 2);
+    assert!(get(&node.addr, 1).is_some());
+
+    std::thread::sleep(Duration::from_millis(1_200));
+    assert_eq!(get(&node.addr, 1), None);
+    assert!(data.get(1).is_some(), "purged before the tick");
+
+    node.addr.send_message(Tick());
+    // messages are handled in order, the tick is done once the get is answered
+    assert!(get(&node.addr, 2).is_some());
+    assert!(data.get(1).is_none(), "not purged by the tick");
+    assert!(data.get(2).is_some());
+    stop(vec![node]);
+}
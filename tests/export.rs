@@ -0,0 +1,109 @@
+mod common;
+
+use common::{
+    ask, new_node, put, receive_where, reply_listener, run, start, stop, wait_for_ring, Running,
+};
+use copper::chord::address::Address;
+use copper::chord::consistency::Consistency;
+use copper::chord::entry::{import_entries, now_millis};
+use copper::chord::message::Message::{Dump, MultiPut, Put};
+use copper::chord::route::Route;
+use copper::chord::storage::{MemoryStorage, SharedStorage, Storage};
+use serde_json::{json, Value};
+use std::net::TcpListener;
+
+/// The export of the ring `entry` is in: the entries of every page of its dump, one per line,
+/// as the client writes them.
+fn export(entry: &Address) -> String {
+    let (sock, local): (TcpListener, Address) = reply_listener();
+    entry.send_message(Dump(local, None, Route::new(false)));
+    let mut lines: Vec<String> = Vec::new();
+    loop {
+        let page: Value =
+            receive_where(&sock, "dump_page", |_| true).expect("the dump did not end");
+        for e in page["entries"].as_array().unwrap() {
+            lines.push(e.to_string());
+        }
+        if page["last"].as_bool() == Some(true) {
+            return lines.join("\n");
+        }
+    }
+}
+
+#[test]
+fn an_import_keeps_the_ttl_left_and_skips_what_expired() {
+    let now: i64 = 1_000_000;
+    let content: String = [
+        json!({"key" : 1, "value" : 1.5, "expires_at" : now + 1_500}),
+        json!({"key" : 2, "value" : 2.5, "expires_at" : now}),
+        json!({"key" : 3, "value" : 3.5, "expires_at" : null}),
+    ]
+    .iter()
+    .map(|e| e.to_string())
+    .collect::<Vec<String>>()
+    .join("\n\n");
+    let entries: Vec<Value> = import_entries(&content, now).unwrap();
+    assert_eq!(
+        entries,
+        vec![
+            json!({"key" : 1, "value" : 1.5, "ttl" : 2}),
+            json!({"key" : 3, "value" : 3.5, "ttl" : null}),
+        ]
+    );
+    assert_eq!(import_entries("{\"key\" : 4}", now), None);
+    assert_eq!(import_entries("not json", now), None);
+}
+
+#[test]
+fn an_export_imported_into_another_ring_gives_back_its_entries() {
+    let first: Running = start(0, None);
+    let second: Running = start(16, Some(&first.addr));
+    let nodes: Vec<Running> = vec![first, second];
+    wait_for_ring(&nodes);
+    put(&nodes[0].addr, 3, 1.5, 1);
+    put(&nodes[0].addr, 20, 2.5, 2);
+    ask(
+        &nodes[0].addr,
+        |local| {
+            Put(
+                local,
+                9,
+                3.5,
+                3,
+                Some(60),
+                Consistency::One,
+                Route::new(false),
+            )
+        },
+        "ack",
+        "id",
+        3,
+    )
+    .expect("put not acked");
+    let before: i64 = now_millis();
+
+    let content: String = export(&nodes[1].addr);
+    stop(nodes);
+    let entries: Vec<Value> = import_entries(&content, now_millis()).unwrap();
+    assert_eq!(entries.len(), 3);
+    assert!(entries.iter().all(|e| e["ttl"].is_null() || e["ttl"] == 60));
+
+    let data: SharedStorage = SharedStorage::new(Box::new(MemoryStorage::new()));
+    let target: Running = run(new_node(5, data.clone()));
+    ask(
+        &target.addr,
+        |local| MultiPut(local, json!(entries), 4, Route::new(false)),
+        "multi_answer",
+        "id",
+        4,
+    )
+    .expect("import not answered");
+    for (key, value) in [(3, 1.5), (20, 2.5), (9, 3.5)] {
+        assert_eq!(data.get(key).map(|e| e.get_value()), Some(value));
+    }
+    assert_eq!(data.get(3).unwrap().get_expires_at(), None);
+    // the entry expires about when it would have in the exported ring
+    let expires_at: i64 = data.get(9).unwrap().get_expires_at().unwrap();
+    assert!(expires_at >= before + 59_000 && expires_at <= now_millis() + 60_000);
+    stop(vec![target]);
+}
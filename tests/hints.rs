@@ -0,0 +1,144 @@
+mod common;
+
+use common::{
+    ask, free_port, get, new_node, put, receive_where, recover_beside, run, start, stop, Running,
+};
+use copper::chord::address::Address;
+use copper::chord::consistency::Consistency;
+use copper::chord::entry::{data_from_json, data_to_json, Entry, Version};
+use copper::chord::message::Message;
+use copper::chord::message::Message::{Hinted, Put, Tick};
+use copper::chord::node::Node;
+use copper::chord::route::Route;
+use copper::chord::state::StateFile;
+use copper::chord::storage::{MemoryStorage, SharedStorage};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{read_to_string, remove_file};
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::{Path, PathBuf};
+
+fn hinted(from: &Address, key: i64, entry: Entry) -> Message {
+    let mut data: HashMap<i64, Entry> = HashMap::new();
+    data.insert(key, entry);
+    Hinted(from.clone(), data_to_json(&data), Route::new(false))
+}
+
+fn saved_hints(path: &Path) -> Vec<Value> {
+    let state: Value = serde_json::from_str(&read_to_string(path).unwrap()).unwrap();
+    state["hints"].as_array().cloned().unwrap_or_default()
+}
+
+#[test]
+fn a_hinted_write_only_wins_over_older_versions() {
+    let node: Running = start(3, None);
+    put(&node.addr, 1, 1.0, 1);
+    let (_, written): (f64, Version) = get(&node.addr, 1).unwrap();
+
+    // kept by another node before the put above, it must not undo it
+    let older: Version = Version::new(written.get_counter() - 1, 7);
+    node.addr
+        .send_message(hinted(&node.addr, 1, Entry::new(9.0, older, None)));
+    assert_eq!(get(&node.addr, 1), Some((1.0, written)));
+
+    let newer: Version = Version::new(written.get_counter() + 100, 7);
+    node.addr
+        .send_message(hinted(&node.addr, 1, Entry::new(5.0, newer, None)));
+    node.addr
+        .send_message(hinted(&node.addr, 2, Entry::new(2.0, older, None)));
+    assert_eq!(get(&node.addr, 1), Some((5.0, newer)));
+    assert_eq!(get(&node.addr, 2), Some((2.0, older)));
+
+    // the clock moved past the replayed version
+    put(&node.addr, 3, 3.0, 2);
+    assert!(get(&node.addr, 3).unwrap().1 > newer);
+    stop(vec![node]);
+}
+
+#[test]
+fn a_write_for_an_unreachable_owner_is_kept_across_a_restart_then_replayed() {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("copper-hints-{}.state", std::process::id()));
+    let _ = remove_file(&path);
+    // the node 20 owns (10, 20] but nothing listens on its port yet
+    let owner_port: i64 = free_port();
+    let owner: Address = Address::new(Ipv4Addr::LOCALHOST, owner_port, 20);
+
+    let mut n: Node<MemoryStorage> = new_node(10, MemoryStorage::new());
+    recover_beside(&mut n, &owner, &path);
+    let node: Running = run(n);
+
+    // the requester is answered once the write is kept
+    put(&node.addr, 15, 2.5, 1);
+    // a write needing more copies than the hint fails
+    let refused: Value = ask(
+        &node.addr,
+        |local| Put(local, 16, 1.0, 2, None, Consistency::All, Route::new(false)),
+        "error",
+        "id",
+        2,
+    )
+    .expect("the put needing every copy did not fail");
+    assert!(refused["reason"].as_str().unwrap().contains("hint"));
+    // messages are handled in order, the puts are done once the get is answered
+    assert_eq!(get(&node.addr, 10), None);
+    let hints: Vec<Value> = saved_hints(&path);
+    assert_eq!(hints.len(), 2);
+    assert_eq!(hints[0]["message"]["cmd"], "hinted");
+    stop(vec![node]);
+
+    let mut n: Node<MemoryStorage> = new_node(10, MemoryStorage::new());
+    assert!(n.recover(StateFile::new(path.clone())));
+    let node: Running = run(n);
+    let sock: TcpListener = TcpListener::bind((Ipv4Addr::LOCALHOST, owner_port as u16)).unwrap();
+    sock.set_nonblocking(true).unwrap();
+    node.addr.send_message(Tick());
+    let replayed: Value = receive_where(&sock, "hinted", |args| {
+        data_from_json(&args["data"]).contains_key(&15)
+    })
+    .expect("the hint was not replayed");
+    let entry: Entry = data_from_json(&replayed["data"]).remove(&15).unwrap();
+    assert_eq!(entry.get_value(), 2.5);
+    // versioned by the node which kept it, when it kept it
+    assert_eq!(entry.get_version().get_writer(), 10);
+
+    assert_eq!(get(&node.addr, 10), None);
+    assert!(saved_hints(&path).is_empty());
+    stop(vec![node]);
+    let _ = remove_file(&path);
+}
+
+#[test]
+fn a_hint_kept_by_a_node_whose_clock_lags_still_wins_over_older_writes() {
+    let owner_path: PathBuf =
+        std::env::temp_dir().join(format!("copper-hints-owner-{}.state", std::process::id()));
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("copper-hints-lagging-{}.state", std::process::id()));
+    // the node 20 owns (10, 20], the node 10 forwards its writes
+    let data: SharedStorage = SharedStorage::new(Box::new(MemoryStorage::new()));
+    let mut o: Node<SharedStorage> = new_node(20, data.clone());
+    let owner: Address = o.get_addr();
+    let mut n: Node<MemoryStorage> = new_node(10, MemoryStorage::new());
+    recover_beside(&mut o, &n.get_addr(), &owner_path);
+    recover_beside(&mut n, &owner, &path);
+
+    // the owner's clock goes far ahead of the forwarder's, which writes nothing itself
+    let first: Running = run(o);
+    for key in 11..=20 {
+        put(&owner, key, 1.0, key);
+    }
+    stop(vec![first]);
+
+    let node: Running = run(n);
+    put(&node.addr, 15, 2.5, 1);
+    let mut o: Node<SharedStorage> = Node::new(Ipv4Addr::LOCALHOST, owner.get_port(), 20, data);
+    assert!(o.recover(StateFile::new(owner_path.clone())));
+    let back: Running = run(o);
+    node.addr.send_message(Tick());
+    // the get goes through the forwarder after its tick replayed the hint
+    assert_eq!(get(&node.addr, 15).map(|(v, _)| v), Some(2.5));
+    assert_eq!(get(&owner, 16).map(|(v, _)| v), Some(1.0));
+    stop(vec![node, back]);
+    let _ = remove_file(&path);
+    let _ = remove_file(&owner_path);
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use common::{ask, get, put, start, stop, Running, REPLY_TIMEOUT};
+use copper::chord::message::Message::Scan;
+use copper::chord::route::Route;
+use serde_json::Value;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+#[test]
+fn a_scan_with_extreme_bounds_is_answered() {
+    let node: Running = start(3, None);
+    for key in [-2, 5, 40] {
+        put(&node.addr, key, 1.0, key);
+    }
+    for (start, end) in [(-2, i64::MAX), (i64::MIN, i64::MAX), (i64::MIN, 0)] {
+        let page: Value = ask(
+            &node.addr,
+            |local| Scan(local, start, end, None, Route::new(false)),
+            "scan_page",
+            "id",
+            3,
+        )
+        .unwrap_or_else(|| panic!("scan of [{}, {}] not answered", start, end));
+        assert_eq!(page["last"].as_bool(), Some(true));
+        let keys: Vec<i64> = page["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|e| e["key"].as_i64())
+            .collect();
+        assert!(keys.iter().all(|&k| k >= start && k <= end));
+    }
+    get(&node.addr, 1);
+    stop(vec![node]);
+}
+
+#[test]
+fn a_connection_left_open_does_not_block_the_node() {
+    let node: Running = start(3, None);
+    let silent: TcpStream =
+        TcpStream::connect(format!("{}:{}", node.addr.get_ip(), node.addr.get_port())).unwrap();
+    let started: Instant = Instant::now();
+    get(&node.addr, 1);
+    assert!(started.elapsed() < REPLY_TIMEOUT - Duration::from_secs(1));
+    drop(silent);
+    stop(vec![node]);
+}
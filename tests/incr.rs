@@ -0,0 +1,82 @@
+mod common;
+
+use common::{ask, get, new_node, run, start, stop, wait_for_ring, Running};
+use copper::chord::address::Address;
+use copper::chord::consistency::Consistency;
+use copper::chord::message::Message::{Incr, Put};
+use copper::chord::route::Route;
+use copper::chord::storage::{MemoryStorage, SharedStorage, Storage};
+use serde_json::Value;
+use std::thread::JoinHandle;
+
+/// Adds `delta` to `key` through `node`, returns the value it answered with.
+fn incr(node: &Address, key: i64, delta: f64) -> f64 {
+    let answer: Value = ask(
+        node,
+        |local| Incr(local, key, delta, Route::new(false)),
+        "answer",
+        "key",
+        key,
+    )
+    .unwrap_or_else(|| panic!("incr of {} not answered", key));
+    answer["value"].as_f64().unwrap()
+}
+
+#[test]
+fn increments_sent_at_once_through_several_nodes_all_count() {
+    let first: Running = start(0, None);
+    let second: Running = start(10, Some(&first.addr));
+    let third: Running = start(20, Some(&first.addr));
+    let nodes: Vec<Running> = vec![first, second, third];
+    wait_for_ring(&nodes);
+    // 3 is owned by 10, two of the senders go through another node
+    let senders: Vec<JoinHandle<()>> = nodes
+        .iter()
+        .map(|n| {
+            let addr: Address = n.addr.clone();
+            std::thread::spawn(move || {
+                for _ in 0..20 {
+                    incr(&addr, 3, 1.0);
+                }
+            })
+        })
+        .collect();
+    for sender in senders {
+        sender.join().unwrap();
+    }
+    assert_eq!(get(&nodes[2].addr, 3).map(|(v, _)| v), Some(60.0));
+    stop(nodes);
+}
+
+#[test]
+fn an_increment_keeps_the_expiry_of_the_entry() {
+    let data: SharedStorage = SharedStorage::new(Box::new(MemoryStorage::new()));
+    let node: Running = run(new_node(3, data.clone()));
+    ask(
+        &node.addr,
+        |local| {
+            Put(
+                local,
+                5,
+                1.5,
+                1,
+                Some(60),
+                Consistency::One,
+                Route::new(false),
+            )
+        },
+        "ack",
+        "id",
+        1,
+    )
+    .expect("put not acked");
+    let expires_at: Option<i64> = data.get(5).unwrap().get_expires_at();
+    assert!(expires_at.is_some());
+
+    assert_eq!(incr(&node.addr, 5, 2.0), 3.5);
+    assert_eq!(data.get(5).unwrap().get_expires_at(), expires_at);
+    // a key with no entry yet starts from the delta and never expires
+    assert_eq!(incr(&node.addr, 6, 2.0), 2.0);
+    assert_eq!(data.get(6).unwrap().get_expires_at(), None);
+    stop(vec![node]);
+}
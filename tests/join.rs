@@ -0,0 +1,176 @@
+mod common;
+
+use common::{
+    free_port, get, put, receive, receive_where, reply_listener, run, start, stop, wait_for_ring,
+    Running,
+};
+use copper::chord::address::Address;
+use copper::chord::entry::{data_to_json, Entry, Version};
+use copper::chord::message::Message::{HelloOK, JoinCommit, Tick, Transfer};
+use copper::chord::node::{Node, MAX_NODE};
+use copper::chord::route::Route;
+use copper::chord::state::StateFile;
+use copper::chord::storage::{MemoryStorage, SharedStorage};
+use rand::seq::SliceRandom;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::remove_file;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::PathBuf;
+
+/// Number of nodes joining the ring of the first one all at once.
+const JOINERS: usize = 15;
+
+#[test]
+fn concurrent_joins_leave_a_consistent_ring() {
+    let first: Running = start(0, None);
+    for key in 0..MAX_NODE {
+        put(&first.addr, key, key as f64 * 1.5, key);
+    }
+
+    let mut others: Vec<i64> = (1..MAX_NODE).collect();
+    others.shuffle(&mut rand::thread_rng());
+    let entry: Address = first.addr.clone();
+    let mut nodes: Vec<Running> = vec![first];
+    for &id in &others[..JOINERS] {
+        nodes.push(start(id, Some(&entry)));
+    }
+    wait_for_ring(&nodes);
+
+    for key in 0..MAX_NODE {
+        let from: &Running = nodes.choose(&mut rand::thread_rng()).unwrap();
+        assert_eq!(
+            get(&from.addr, key).map(|(v, _)| v),
+            Some(key as f64 * 1.5),
+            "{} is lost",
+            key
+        );
+    }
+    stop(nodes);
+}
+
+fn from_joiner(args: &Value) -> bool {
+    args["address"]["id"].as_i64() == Some(10)
+}
+
+#[test]
+fn a_joiner_drops_the_keys_of_a_handoff_given_up() {
+    // the node 20 is a listener of the test, the joiner's successor and previous node
+    let (sock, local): (TcpListener, Address) = reply_listener();
+    let successor: Address = Address::new(local.get_ip(), local.get_port(), 20);
+    let joiner: Running = start(10, Some(&successor));
+    receive_where(&sock, "hello", from_joiner).expect("no hello from 10");
+
+    let mut data: HashMap<i64, Entry> = HashMap::new();
+    data.insert(5, Entry::new(5.0, Version::new(1, 20), None));
+    joiner
+        .addr
+        .send_message(Transfer(successor.clone(), data_to_json(&data), 0));
+    receive(&sock, "transfer_ack", "seq", 0).expect("the chunk was not acknowledged");
+
+    // the successor gave up, then 5 got deleted before the joiner said hello again
+    for _ in 0..2 {
+        joiner.addr.send_message(Tick());
+    }
+    receive_where(&sock, "hello", from_joiner).expect("no second hello from 10");
+    data.clear();
+    joiner
+        .addr
+        .send_message(Transfer(successor.clone(), data_to_json(&data), 0));
+    receive(&sock, "transfer_ack", "seq", 0).expect("the new handoff was not acknowledged");
+    joiner.addr.send_message(HelloOK(
+        10,
+        successor.clone(),
+        data_to_json(&data),
+        successor.clone(),
+        Route::new(false),
+    ));
+    receive_where(&sock, "join_confirm", from_joiner).expect("the join was not confirmed");
+    joiner.addr.send_message(JoinCommit(
+        successor.clone(),
+        successor.clone(),
+        data_to_json(&data),
+    ));
+    assert_eq!(get(&joiner.addr, 5), None);
+    stop(vec![joiner]);
+}
+
+/// A node of a ring restarted at once, which keeps its port, its data and its state file.
+struct Restarting {
+    id: i64,
+    port: i64,
+    data: SharedStorage,
+    path: PathBuf,
+}
+
+impl Restarting {
+    fn new(id: i64) -> Restarting {
+        let path: PathBuf = std::env::temp_dir().join(format!(
+            "copper-restart-{}-{}.state",
+            std::process::id(),
+            id
+        ));
+        let _ = remove_file(&path);
+        Restarting {
+            id,
+            port: free_port(),
+            data: SharedStorage::new(Box::new(MemoryStorage::new())),
+            path,
+        }
+    }
+
+    /// Starts the node, through its saved successor when it has a saved state, else through
+    /// `entry`.
+    fn start(&self, entry: Option<&Address>) -> Running {
+        let mut n: Node<SharedStorage> =
+            Node::new(Ipv4Addr::LOCALHOST, self.port, self.id, self.data.clone());
+        if n.recover(StateFile::new(self.path.clone())) {
+            let successor: Address = n.get_successor();
+            n.join(successor);
+        } else if let Some(entry) = entry {
+            n.join(entry.clone());
+        }
+        run(n)
+    }
+}
+
+#[test]
+fn a_whole_ring_restarted_at_once_joins_again() {
+    let ring: Vec<Restarting> = [0, 16, 24].iter().map(|&id| Restarting::new(id)).collect();
+    let first: Running = ring[0].start(None);
+    let entry: Address = first.addr.clone();
+    let mut nodes: Vec<Running> = vec![first];
+    for r in &ring[1..] {
+        nodes.push(r.start(Some(&entry)));
+    }
+    wait_for_ring(&nodes);
+    for key in 0..MAX_NODE {
+        put(&entry, key, key as f64, key);
+    }
+    // every node saves its state once it handled the get after the puts
+    for n in &nodes {
+        get(&n.addr, 0);
+    }
+    stop(nodes);
+
+    // every node rejoins through its successor, itself rejoining, and a new node joins
+    // through one of them meanwhile
+    let mut nodes: Vec<Running> = ring.iter().map(|r| r.start(None)).collect();
+    let entry: Address = nodes[1].addr.clone();
+    nodes.push(start(8, Some(&entry)));
+    wait_for_ring(&nodes);
+    for key in 0..MAX_NODE {
+        for n in &nodes {
+            assert_eq!(
+                get(&n.addr, key).map(|(v, _)| v),
+                Some(key as f64),
+                "{} lost",
+                key
+            );
+        }
+    }
+    stop(nodes);
+    for r in &ring {
+        let _ = remove_file(&r.path);
+    }
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use common::{free_port, receive, reply_listener, start, stop, Running};
+use copper::chord::address::Address;
+use copper::chord::lookup::{lookup_iterative, Lookup};
+use copper::chord::message::Message::NextHop;
+use copper::chord::node::get_addr_from_json;
+use serde_json::{json, Value};
+use std::net::{Ipv4Addr, TcpListener};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// A node of the test answering the first `FindNext` about `key` it gets with `candidates`,
+/// to be asked in that order.
+fn first_hop(key: i64, candidates: Vec<Address>) -> (Address, JoinHandle<()>) {
+    let (sock, local): (TcpListener, Address) = reply_listener();
+    let addr: Address = Address::new(Ipv4Addr::LOCALHOST, local.get_port(), 2);
+    let hop: Address = addr.clone();
+    let handle: JoinHandle<()> = std::thread::spawn(move || {
+        let ask: Value = receive(&sock, "find_next", "key", key).expect("no lookup came");
+        let from: Address = get_addr_from_json(&ask, "address").unwrap();
+        let candidates: Vec<Value> = candidates
+            .iter()
+            .map(|a| json!({"address" : a.to_json()}))
+            .collect();
+        from.send_message(NextHop(hop, key, None, json!(candidates)));
+    });
+    (addr, handle)
+}
+
+#[test]
+fn a_lookup_skips_the_hops_it_cannot_reach_or_that_do_not_answer() {
+    let owner: Running = start(20, None);
+    // nothing listens on the first, the second never answers
+    let unreachable: Address = Address::new(Ipv4Addr::LOCALHOST, free_port(), 8);
+    let (silent, local): (TcpListener, Address) = reply_listener();
+    let silent_addr: Address = Address::new(Ipv4Addr::LOCALHOST, local.get_port(), 12);
+    let (start_addr, hop): (Address, JoinHandle<()>) =
+        first_hop(15, vec![unreachable, silent_addr, owner.addr.clone()]);
+
+    let found: Lookup = lookup_iterative(
+        &start_addr,
+        Ipv4Addr::LOCALHOST,
+        15,
+        Duration::from_millis(500),
+    )
+    .expect("the lookup gave up");
+    hop.join().unwrap();
+    assert_eq!(found.get_owner(), owner.addr);
+    assert_eq!(found.get_path(), vec![start_addr, owner.addr.clone()]);
+    drop(silent);
+    stop(vec![owner]);
+}
@@ -0,0 +1,56 @@
+use copper::chord::entry::{Entry, Version};
+use copper::chord::merkle::MerkleTree;
+use serde_json::{json, Value};
+
+fn entry(value: f64, counter: i64) -> Entry {
+    Entry::new(value, Version::new(counter, 1), None)
+}
+
+fn tree(entries: &[(i64, Entry)]) -> MerkleTree {
+    MerkleTree::build(entries.iter().cloned())
+}
+
+#[test]
+fn the_tree_is_the_same_on_every_build() {
+    // a process picking a random seed would give other hashes
+    assert_eq!(tree(&[]).root(), 8139387217014739733);
+    assert_eq!(tree(&[(5, entry(1.5, 3))]).root(), 10706128755693743488);
+}
+
+#[test]
+fn the_order_of_the_entries_does_not_matter() {
+    // 3 and 35 share the ring position 3
+    let entries: Vec<(i64, Entry)> =
+        vec![(3, entry(1.0, 1)), (35, entry(2.0, 2)), (20, entry(3.0, 3))];
+    let mut reversed: Vec<(i64, Entry)> = entries.clone();
+    reversed.reverse();
+    assert_eq!(tree(&entries), tree(&reversed));
+    assert!(tree(&entries).diff(&tree(&reversed)).is_empty());
+    assert_ne!(tree(&entries).root(), tree(&[]).root());
+}
+
+#[test]
+fn diff_lists_the_positions_that_differ() {
+    let base: Vec<(i64, Entry)> =
+        vec![(1, entry(1.0, 1)), (17, entry(2.0, 2)), (31, entry(3.0, 3))];
+    let mut other: Vec<(i64, Entry)> = base.clone();
+    // a newer version of the same value, a missing entry and an extra one at -2, position 30
+    other[0] = (1, entry(1.0, 4));
+    other.remove(2);
+    other.push((-2, entry(4.0, 5)));
+    assert_eq!(tree(&base).diff(&tree(&other)), vec![1, 30, 31]);
+    assert_eq!(tree(&other).diff(&tree(&base)), vec![1, 30, 31]);
+
+    let expiring: Vec<(i64, Entry)> = vec![(17, Entry::new(2.0, Version::new(2, 1), Some(10)))];
+    assert_eq!(tree(&base[1..2]).diff(&tree(&expiring)), vec![17]);
+    assert_eq!(tree(&[]).diff(&tree(&base)), vec![1, 17, 31]);
+}
+
+#[test]
+fn the_tree_goes_through_json() {
+    let t: MerkleTree = tree(&[(9, entry(9.0, 9))]);
+    assert_eq!(MerkleTree::from_json(&t.to_json()), Some(t.clone()));
+    let short: Value = json!([1, 2, 3]);
+    assert_eq!(MerkleTree::from_json(&short), None);
+    assert_eq!(MerkleTree::from_json(&json!("tree")), None);
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use common::{put, receive_next, reply_listener, start, start_with, stop, wait_for_ring, Running};
+use copper::chord::address::Address;
+use copper::chord::consistency::Consistency;
+use copper::chord::message::Message;
+use copper::chord::message::Message::{Get, Moved, Put};
+use copper::chord::node::get_addr_from_json;
+use copper::chord::route::Route;
+use serde_json::Value;
+use std::net::TcpListener;
+
+/// Ids of the nodes a traced put from `node` went through.
+fn put_trace(node: &Address, key: i64, id: i64) -> Vec<i64> {
+    let ack: Value = put(node, key, 1.0, id);
+    Route::from_json(&ack["route"]).get_trace().unwrap()
+}
+
+#[test]
+fn a_moved_reply_naming_the_owner_fills_the_cache() {
+    let first: Running = start(0, None);
+    let mut nodes: Vec<Running> = vec![first];
+    for id in [8, 16, 24] {
+        let entry: Address = nodes[0].addr.clone();
+        nodes.push(start(id, Some(&entry)));
+    }
+    wait_for_ring(&nodes);
+    let (from, hop, owner): (&Address, &Address, &Address) =
+        (&nodes[0].addr, &nodes[1].addr, &nodes[2].addr);
+
+    // the owner is unknown, the put goes through the finger before the key
+    from.send_message(Moved(12, owner.clone(), hop.clone(), None, None));
+    assert_eq!(put_trace(from, 12, 1), vec![0, 8, 16]);
+
+    // 8 tells that its successor 16 owns the keys after it
+    from.send_message(Moved(12, hop.clone(), owner.clone(), Some(8), None));
+    assert_eq!(put_trace(from, 12, 2), vec![0, 16]);
+    assert_eq!(put_trace(from, 9, 3), vec![0, 16]);
+
+    // a node which does not know the range only makes the cache forget it
+    from.send_message(Moved(12, owner.clone(), hop.clone(), None, None));
+    assert_eq!(put_trace(from, 12, 4), vec![0, 8, 16]);
+    stop(nodes);
+}
+
+/// Sends `request` to `node`, then retries it wherever a `moved` reply points to, the way the
+/// client does. Returns the last reply and the ids of the nodes it was redirected to.
+fn follow(sock: &TcpListener, node: &Address, request: Message) -> (Value, Vec<i64>) {
+    node.send_message(request.clone());
+    let mut redirected: Vec<i64> = Vec::new();
+    loop {
+        let reply: Value = receive_next(sock).expect("the request was not answered");
+        if reply["cmd"] != "moved" {
+            return (reply, redirected);
+        }
+        let args: &Value = &reply["args"];
+        // the reply carries the request as it was sent, the route it took aside
+        assert_eq!(args["request"]["cmd"], request.to_json()["cmd"]);
+        assert_eq!(args["request"]["args"]["key"], 12);
+        let next: Address = get_addr_from_json(args, "next").unwrap();
+        assert!(redirected.len() < 4, "redirected round the ring");
+        redirected.push(next.get_id());
+        next.send_json(args["request"].clone());
+    }
+}
+
+#[test]
+fn a_request_to_a_node_not_owning_its_key_comes_back_with_the_node_to_retry_at() {
+    let first: Running = start_with(0, None, |n| n.set_redirect(true));
+    let mut nodes: Vec<Running> = vec![first];
+    for id in [8, 16, 24] {
+        let entry: Address = nodes[0].addr.clone();
+        nodes.push(start_with(id, Some(&entry), |n| n.set_redirect(true)));
+    }
+    wait_for_ring(&nodes);
+
+    let (sock, local): (TcpListener, Address) = reply_listener();
+    let put: Message = Put(
+        local.clone(),
+        12,
+        4.5,
+        1,
+        None,
+        Consistency::One,
+        Route::new(false),
+    );
+    let (ack, redirected): (Value, Vec<i64>) = follow(&sock, &nodes[0].addr, put);
+    assert_eq!(ack["cmd"], "ack");
+    assert_eq!(ack["args"]["id"], 1);
+    assert!(!redirected.is_empty());
+    assert_eq!(redirected.last(), Some(&16));
+
+    let get: Message = Get(local.clone(), 12, Consistency::One, Route::new(false));
+    let (answer, _): (Value, Vec<i64>) = follow(&sock, &nodes[3].addr, get);
+    assert_eq!(answer["cmd"], "answer");
+    assert_eq!(answer["args"]["value"], 4.5);
+    // the owner itself answers at once
+    let get: Message = Get(local, 12, Consistency::One, Route::new(false));
+    let (_, redirected): (Value, Vec<i64>) = follow(&sock, &nodes[2].addr, get);
+    assert!(redirected.is_empty());
+    stop(nodes);
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::{ask, get, put, start, start_with, stop, wait_for_ring, Running, SETTLE_TIMEOUT};
+use copper::chord::address::Address;
+use copper::chord::message::Message::{FindNext, Probed, Tick};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+/// Ids of the distinct fingers of `node`, sorted.
+fn fingers(node: &Address) -> Vec<i64> {
+    // every finger lies before the key just under the node's own id
+    let key: i64 = node.get_id() - 1;
+    let hop: Value =
+        ask(node, |local| FindNext(local, key), "next_hop", "key", key).expect("no next hop");
+    let mut ids: Vec<i64> = hop["candidates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|c| c["address"]["id"].as_i64())
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+/// Tells `node` the round trip times `rtts` to the nodes `nodes`, often enough for its smoothed
+/// times to follow them.
+fn report_rtts(node: &Address, nodes: &[Running], rtts: &[i64]) {
+    let results: Vec<Value> = nodes
+        .iter()
+        .zip(rtts)
+        .map(|(n, rtt)| json!({"address" : n.addr.to_json(), "rtt" : rtt}))
+        .collect();
+    for _ in 0..40 {
+        node.send_message(Probed(json!(results)));
+    }
+}
+
+#[test]
+fn fingers_picked_by_round_trip_time_keep_the_ring_routing() {
+    let first: Running = start_with(0, None, |n| n.set_proximity(true));
+    let mut nodes: Vec<Running> = vec![first];
+    for id in [5, 11, 19, 26] {
+        let entry: Address = nodes[0].addr.clone();
+        nodes.push(start_with(id, Some(&entry), |n| n.set_proximity(true)));
+    }
+    wait_for_ring(&nodes);
+    for key in 0..32 {
+        put(&nodes[0].addr, key, key as f64, key);
+    }
+    for _ in 0..3 {
+        for n in &nodes {
+            n.addr.send_message(Tick());
+        }
+        // the neighbours are measured by another thread, the tick does not hold up requests
+        let started: Instant = Instant::now();
+        assert_eq!(get(&nodes[2].addr, 3).map(|(v, _)| v), Some(3.0));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        std::thread::sleep(Duration::from_millis(300));
+    }
+    wait_for_ring(&nodes);
+    for key in 0..32 {
+        for n in &nodes {
+            assert_eq!(
+                get(&n.addr, key).map(|(v, _)| v),
+                Some(key as f64),
+                "{} not found from {:?}",
+                key,
+                n.addr
+            );
+        }
+    }
+    stop(nodes);
+}
+
+#[test]
+fn a_finger_goes_to_the_closest_node_of_its_slot() {
+    let first: Running = start_with(0, None, |n| n.set_proximity(true));
+    let mut nodes: Vec<Running> = vec![first];
+    for id in [16, 20, 24] {
+        let entry: Address = nodes[0].addr.clone();
+        nodes.push(start(id, Some(&entry)));
+    }
+    wait_for_ring(&nodes);
+    let me: Address = nodes[0].addr.clone();
+    // only the farthest finger, 16, has several nodes in its slot [16, 0)
+    assert_eq!(fingers(&me), vec![16]);
+
+    // the ticks make the node discover the nodes of its slots, then 20 is the closest
+    let deadline: Instant = Instant::now() + SETTLE_TIMEOUT;
+    loop {
+        me.send_message(Tick());
+        std::thread::sleep(Duration::from_millis(300));
+        report_rtts(&me, &nodes[1..], &[50_000, 10, 90_000]);
+        if fingers(&me) == vec![16, 20] {
+            break;
+        }
+        assert!(Instant::now() < deadline, "20 never became a finger");
+    }
+    // then 24 is
+    report_rtts(&me, &nodes[1..], &[50_000, 90_000, 10]);
+    assert_eq!(fingers(&me), vec![16, 24]);
+    // and back to the first node of the slot
+    report_rtts(&me, &nodes[1..], &[10, 90_000, 90_000]);
+    assert_eq!(fingers(&me), vec![16]);
+    stop(nodes);
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use common::{
+    free_port, get, new_node, put, receive, receive_where, recover_beside, run, start, stop,
+    wait_for_ring, Running,
+};
+use copper::chord::address::Address;
+use copper::chord::entry::data_from_json;
+use copper::chord::message::Message::{Move, TransferAck};
+use copper::chord::node::{plan_move, split_position, Node};
+use copper::chord::storage::MemoryStorage;
+use serde_json::{json, Value};
+use std::fs::remove_file;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::PathBuf;
+
+fn address(id: i64) -> Address {
+    Address::new(Ipv4Addr::LOCALHOST, 17000 + id, id)
+}
+
+fn report(id: i64, load: i64, split: Option<i64>) -> Value {
+    json!({"address" : address(id).to_json(), "load" : load, "split" : split})
+}
+
+fn keys(data: &Value) -> Vec<i64> {
+    let mut keys: Vec<i64> = data_from_json(data).into_keys().collect();
+    keys.sort_unstable();
+    keys
+}
+
+#[test]
+fn the_least_loaded_node_moves_next_to_the_most_loaded_one() {
+    let reports: Vec<Value> = vec![
+        report(3, 10, Some(1)),
+        report(9, 2, None),
+        report(20, 50, Some(15)),
+    ];
+    assert_eq!(plan_move(&reports), Some((address(9), address(20), 15)));
+
+    // the gap is not large enough
+    let even: Vec<Value> = vec![report(3, 10, Some(1)), report(9, 6, None)];
+    assert_eq!(plan_move(&even), None);
+    // nowhere to split the most loaded node
+    let unsplittable: Vec<Value> = vec![report(3, 10, None), report(9, 1, None)];
+    assert_eq!(plan_move(&unsplittable), None);
+    assert_eq!(plan_move(&reports[..1]), None);
+    assert_eq!(plan_move(&[]), None);
+}
+
+#[test]
+fn the_split_leaves_half_of_the_weight_below_it() {
+    let ones: Vec<(i64, i64)> = [11, 12, 13, 14].iter().map(|&k| (k, 1)).collect();
+    assert_eq!(split_position(10, 20, &ones), Some(12));
+    // keys are taken modulo the ring, the interval wraps around its end
+    let wrapped: Vec<(i64, i64)> = [29, 30, 63, 32].iter().map(|&k| (k, 1)).collect();
+    assert_eq!(split_position(28, 4, &wrapped), Some(30));
+    // one heavy key outweighs the others
+    assert_eq!(
+        split_position(10, 20, &[(11, 1), (12, 1), (18, 10)]),
+        Some(18)
+    );
+    // the split stays below my own id, the joiner takes at least one position
+    assert_eq!(split_position(10, 20, &[(20, 1)]), Some(19));
+    assert_eq!(split_position(10, 20, &[(11, 5)]), Some(11));
+    assert_eq!(split_position(10, 11, &[(11, 1)]), None);
+    assert_eq!(split_position(10, 20, &[]), None);
+}
+
+#[test]
+fn a_moving_node_keeps_its_keys_until_its_successor_acknowledged_them() {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("copper-move-{}.state", std::process::id()));
+    // the node 20 is a listener of the test
+    let port: i64 = free_port();
+    let successor: Address = Address::new(Ipv4Addr::LOCALHOST, port, 20);
+    let sock: TcpListener = TcpListener::bind((Ipv4Addr::LOCALHOST, port as u16)).unwrap();
+    sock.set_nonblocking(true).unwrap();
+
+    let mut n: Node<MemoryStorage> = new_node(10, MemoryStorage::new());
+    recover_beside(&mut n, &successor, &path);
+    let node: Running = run(n);
+    put(&node.addr, 3, 3.0, 3);
+    put(&node.addr, 8, 8.0, 8);
+
+    node.addr.send_message(Move(successor.clone(), 15));
+    let chunk: Value = receive(&sock, "transfer", "seq", 0).expect("no keys transferred");
+    assert_eq!(keys(&chunk["data"]), vec![3, 8]);
+    // not acknowledged yet, the keys are still mine and I still take writes
+    assert_eq!(get(&node.addr, 3).map(|(v, _)| v), Some(3.0));
+    put(&node.addr, 5, 5.0, 5);
+
+    node.addr.send_message(TransferAck(successor.clone(), 0));
+    let written: Value = receive(&sock, "transfer", "seq", 2).expect("no second round");
+    assert_eq!(keys(&written["data"]), vec![5]);
+    node.addr.send_message(TransferAck(successor.clone(), 2));
+    let leave: Option<Value> = receive_where(&sock, "leave", |args| {
+        args["address"]["id"].as_i64() == Some(10)
+    });
+    assert!(leave.is_some(), "no leave from 10");
+    // then it joins again at 15, through the node it moves next to
+    let hello: Option<Value> = receive_where(&sock, "hello", |args| {
+        args["address"]["id"].as_i64() == Some(15)
+    });
+    assert!(hello.is_some(), "no hello from 15");
+    stop(vec![node]);
+    let _ = remove_file(&path);
+}
+
+#[test]
+fn no_key_is_lost_when_a_node_moves() {
+    let first: Running = start(10, None);
+    let second: Running = start(20, Some(&first.addr));
+    let mut nodes: Vec<Running> = vec![first, second];
+    wait_for_ring(&nodes);
+    let stored: Vec<i64> = vec![3, 8, 12, 15, 18, 25];
+    for &key in &stored {
+        put(&nodes[0].addr, key, key as f64, key);
+    }
+
+    nodes[0].addr.send_message(Move(nodes[1].addr.clone(), 15));
+    let moved: &mut Running = &mut nodes[0];
+    moved.addr = Address::new(moved.addr.get_ip(), moved.addr.get_port(), 15);
+    wait_for_ring(&nodes);
+    for &key in &stored {
+        for n in &nodes {
+            assert_eq!(
+                get(&n.addr, key).map(|(v, _)| v),
+                Some(key as f64),
+                "{} lost",
+                key
+            );
+        }
+    }
+    stop(nodes);
+}
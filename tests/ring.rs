@@ -0,0 +1,132 @@
+use copper::chord::node::MAX_NODE;
+use copper::chord::ring::{Interval, RingId};
+
+/// Positions met walking clockwise from `lower` to `upper` one step at a time, a whole turn
+/// when they are the same, without the bounds that are open.
+fn walk<const SIZE: i64>(
+    lower: i64,
+    upper: i64,
+    lower_closed: bool,
+    upper_closed: bool,
+) -> Vec<i64> {
+    let mut steps: Vec<i64> = vec![lower];
+    let mut current: i64 = lower;
+    loop {
+        current = (current + 1) % SIZE;
+        steps.push(current);
+        if current == upper {
+            break;
+        }
+    }
+    if !lower_closed {
+        steps.remove(0);
+    }
+    if !upper_closed {
+        steps.pop();
+    }
+    steps
+}
+
+fn check_arithmetic<const SIZE: i64>() {
+    for a in 0..SIZE {
+        let id: RingId<SIZE> = RingId::new(a);
+        assert_eq!(id.get(), a);
+        assert_eq!(RingId::<SIZE>::new(a - 3 * SIZE), id);
+        assert_eq!(RingId::<SIZE>::new(a + 5 * SIZE), id);
+        assert_eq!(id.distance(id), 0);
+        for steps in -2 * SIZE..2 * SIZE {
+            assert_eq!((id + steps) - steps, id);
+            assert_eq!((id + steps).get(), (a + steps).rem_euclid(SIZE));
+        }
+        for b in 0..SIZE {
+            let other: RingId<SIZE> = RingId::new(b);
+            let d: i64 = id.distance(other);
+            assert!((0..SIZE).contains(&d));
+            assert_eq!(id + d, other);
+            if a != b {
+                assert_eq!(d + other.distance(id), SIZE);
+            }
+        }
+    }
+}
+
+fn check_intervals<const SIZE: i64>() {
+    for lower in 0..SIZE {
+        for upper in 0..SIZE {
+            for &(lower_closed, upper_closed) in
+                &[(false, false), (false, true), (true, false), (true, true)]
+            {
+                let interval: Interval<SIZE> = Interval::new(
+                    RingId::new(lower),
+                    RingId::new(upper),
+                    lower_closed,
+                    upper_closed,
+                );
+                let expected: Vec<i64> = walk::<SIZE>(lower, upper, lower_closed, upper_closed);
+                for id in 0..SIZE {
+                    assert_eq!(
+                        interval.contains(RingId::new(id)),
+                        expected.contains(&id),
+                        "{} in {:?} on a ring of {}",
+                        id,
+                        interval,
+                        SIZE
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn check_named_bounds<const SIZE: i64>() {
+    for lower in 0..SIZE {
+        for upper in 0..SIZE {
+            let (l, u): (RingId<SIZE>, RingId<SIZE>) = (RingId::new(lower), RingId::new(upper));
+            assert_eq!(Interval::open(l, u), Interval::new(l, u, false, false));
+            assert_eq!(Interval::closed(l, u), Interval::new(l, u, true, true));
+            assert_eq!(
+                Interval::open_closed(l, u),
+                Interval::new(l, u, false, true)
+            );
+            assert_eq!(
+                Interval::closed_open(l, u),
+                Interval::new(l, u, true, false)
+            );
+            let width: i64 = Interval::open_closed(l, u).width();
+            assert_eq!(width, if lower == upper { SIZE } else { l.distance(u) });
+            // a node alone owns the whole ring
+            if lower == upper {
+                assert!((0..SIZE).all(|id| Interval::open_closed(l, u).contains(RingId::new(id))));
+            }
+        }
+    }
+}
+
+macro_rules! on_small_rings {
+    ($check:ident) => {
+        $check::<1>();
+        $check::<2>();
+        $check::<3>();
+        $check::<4>();
+        $check::<5>();
+        $check::<6>();
+        $check::<7>();
+        $check::<8>();
+        $check::<MAX_NODE>();
+    };
+}
+
+#[test]
+fn ring_arithmetic_wraps_around() {
+    on_small_rings!(check_arithmetic);
+}
+
+#[test]
+fn intervals_match_a_walk_around_the_ring() {
+    on_small_rings!(check_intervals);
+}
+
+#[test]
+fn named_intervals_have_the_expected_bounds() {
+    on_small_rings!(check_named_bounds);
+}
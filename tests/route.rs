@@ -0,0 +1,71 @@
+mod common;
+
+use common::{receive, reply_listener, start, stop, wait_for_ring, Running};
+use copper::chord::address::Address;
+use copper::chord::message::Message::{Dump, Rebalance};
+use copper::chord::route::{Route, ROUTE_TTL};
+use serde_json::{json, Value};
+use std::net::{Ipv4Addr, TcpListener};
+
+#[test]
+fn every_hop_spends_the_ttl_until_none_is_left() {
+    let mut route: Route = Route::new(true).through(4);
+    for hop in 1..=ROUTE_TTL {
+        route = route.next().unwrap().through(hop);
+        assert_eq!(route.get_hops(), hop);
+        assert_eq!(route.get_ttl(), ROUTE_TTL - hop);
+    }
+    assert!(route.next().is_none());
+    assert_eq!(route.get_trace().unwrap().len() as i64, ROUTE_TTL + 1);
+}
+
+#[test]
+fn a_route_goes_through_json() {
+    let cache: Address = Address::new(Ipv4Addr::LOCALHOST, 4000, 7);
+    let route: Route = Route::new(true).through(3).next().unwrap().cached_by(cache);
+    assert_eq!(Route::from_json(&route.to_json()), route);
+    // older clients send no route
+    assert_eq!(Route::from_json(&Value::Null), Route::new(false));
+}
+
+#[test]
+fn a_forged_route_is_bounded() {
+    let forged: Route = Route::from_json(&json!({"hops" : i64::MAX, "ttl" : i64::MAX}));
+    assert_eq!(forged.get_ttl(), ROUTE_TTL);
+    let next: Route = forged.next().unwrap();
+    assert_eq!(next.get_hops(), i64::MAX);
+    assert_eq!(next.get_ttl(), ROUTE_TTL - 1);
+    assert!(Route::from_json(&json!({"ttl" : -3})).next().is_none());
+}
+
+#[test]
+fn a_walk_whose_origin_left_the_ring_ends_once_its_budget_is_spent() {
+    let first: Running = start(10, None);
+    let second: Running = start(20, Some(&first.addr));
+    let nodes: Vec<Running> = vec![first, second];
+    wait_for_ring(&nodes);
+    // no node is 5 anymore, the walks never get back to it
+    let gone: Address = Address::new(Ipv4Addr::LOCALHOST, 1, 5);
+
+    let (sock, local): (TcpListener, Address) = reply_listener();
+    nodes[0]
+        .addr
+        .send_message(Dump(local, Some(5), Route::new(false)));
+    let error: Value = receive(&sock, "error", "id", 5).expect("the dump did not end");
+    assert!(error["reason"].as_str().unwrap().contains("dump dropped"));
+
+    let (sock, local): (TcpListener, Address) = reply_listener();
+    let report: Value = json!({"address" : gone.to_json(), "load" : 1, "split" : null});
+    nodes[0].addr.send_message(Rebalance(
+        local,
+        "keys".to_string(),
+        json!([report]),
+        Route::new(false),
+    ));
+    let error: Value = receive(&sock, "error", "id", 5).expect("the rebalance did not end");
+    assert!(error["reason"]
+        .as_str()
+        .unwrap()
+        .contains("rebalance dropped"));
+    stop(nodes);
+}
@@ -0,0 +1,126 @@
+use copper::chord::address::Address;
+use copper::chord::node::{key_position, next_hop, MAX_NODE};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+
+/// Number of finger table entries of a node, one per power of two up to half the ring.
+const FINGERS: u32 = 5;
+
+fn address(id: i64) -> Address {
+    Address::new(Ipv4Addr::LOCALHOST, 7000 + id, id)
+}
+
+/// A ring whose nodes all have an exact finger table and previous node.
+struct Ring {
+    ids: Vec<i64>,
+}
+
+impl Ring {
+    fn random<R: Rng>(rng: &mut R) -> Ring {
+        let mut all: Vec<i64> = (0..MAX_NODE).collect();
+        all.shuffle(rng);
+        let size: usize = rng.gen_range(1..=MAX_NODE as usize);
+        let mut ids: Vec<i64> = all[..size].to_vec();
+        ids.sort_unstable();
+        Ring { ids }
+    }
+
+    /// First node at or after the ring position `position`.
+    fn successor(&self, position: i64) -> i64 {
+        match self.ids.iter().find(|&&id| id >= position) {
+            Some(&id) => id,
+            None => self.ids[0],
+        }
+    }
+
+    fn previous(&self, id: i64) -> i64 {
+        match self.ids.iter().rev().find(|&&other| other < id) {
+            Some(&other) => other,
+            None => *self.ids.last().unwrap(),
+        }
+    }
+
+    fn fingers(&self, id: i64) -> HashMap<i64, Address> {
+        (0..FINGERS)
+            .map(|i| {
+                let key: i64 = (id + 2_i64.pow(i)) % MAX_NODE;
+                (key, address(self.successor(key)))
+            })
+            .collect()
+    }
+
+    /// Follows the next hops from `start` until a node answers that it owns `key`, returns
+    /// that node and the number of hops, `None` when the lookup gets lost.
+    fn lookup(&self, start: i64, key: i64) -> Option<(i64, usize)> {
+        let mut current: i64 = start;
+        for hops in 0..=self.ids.len() {
+            let next: Address = next_hop(
+                &address(current),
+                self.previous(current),
+                &self.fingers(current),
+                key,
+            )?;
+            if next.get_id() == current {
+                return Some((current, hops));
+            }
+            current = next.get_id();
+        }
+        None
+    }
+}
+
+#[test]
+fn every_lookup_ends_at_the_true_successor() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..300 {
+        let ring: Ring = Ring::random(&mut rng);
+        let mut keys: Vec<i64> = (0..MAX_NODE).collect();
+        keys.extend((0..8).map(|_| rng.gen_range(-1000..1000)));
+        for &start in &ring.ids {
+            for &key in &keys {
+                let expected: i64 = ring.successor(key_position(key));
+                match ring.lookup(start, key) {
+                    Some((owner, _)) => assert_eq!(
+                        owner, expected,
+                        "lookup of {} from {} in {:?}",
+                        key, start, ring.ids
+                    ),
+                    None => panic!("lookup of {} from {} in {:?} is lost", key, start, ring.ids),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn lookups_take_a_logarithmic_number_of_hops() {
+    let mut rng = rand::thread_rng();
+    for _ in 0..300 {
+        let ring: Ring = Ring::random(&mut rng);
+        for &start in &ring.ids {
+            for key in 0..MAX_NODE {
+                if let Some((_, hops)) = ring.lookup(start, key) {
+                    // each finger hop at least halves the distance, then one last hop to the owner
+                    assert!(
+                        hops <= FINGERS as usize + 1,
+                        "lookup of {} from {} in {:?} took {} hops",
+                        key,
+                        start,
+                        ring.ids,
+                        hops
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn a_single_node_owns_every_key() {
+    let ring: Ring = Ring { ids: vec![7] };
+    for key in -MAX_NODE..2 * MAX_NODE {
+        assert_eq!(ring.lookup(7, key), Some((7, 0)));
+    }
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::{get, new_node, put, run, stop, Running};
+use copper::chord::entry::Version;
+use copper::chord::node::Node;
+use copper::chord::state::StateFile;
+use copper::chord::storage::MemoryStorage;
+use serde_json::Value;
+use std::fs::{read_to_string, remove_file};
+use std::path::{Path, PathBuf};
+
+/// A node with nothing stored, keeping its state in `path`.
+fn recovering(path: &Path) -> (Node<MemoryStorage>, bool) {
+    let mut n: Node<MemoryStorage> = new_node(9, MemoryStorage::new());
+    let recovered: bool = n.recover(StateFile::new(path.to_path_buf()));
+    (n, recovered)
+}
+
+#[test]
+fn the_state_is_only_saved_when_it_changes_and_keeps_the_clock_ahead() {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("copper-state-{}.state", std::process::id()));
+    let _ = remove_file(&path);
+
+    let (n, recovered): (Node<MemoryStorage>, bool) = recovering(&path);
+    assert!(!recovered);
+    let node: Running = run(n);
+    put(&node.addr, 1, 1.0, 1);
+    // the state is saved once the put is handled, after its ack: the get comes after the save
+    get(&node.addr, 1);
+    let saved: String = read_to_string(&path).unwrap();
+    let state: Value = serde_json::from_str(&saved).unwrap();
+    assert!(state["clock"].as_i64().unwrap() > 1);
+    assert!(state.get("put").is_none() && state.get("get").is_none());
+    for id in 2..20 {
+        put(&node.addr, id, 1.0, id);
+        get(&node.addr, id);
+    }
+    // writes and reads left the routing state as it was
+    assert_eq!(read_to_string(&path).unwrap(), saved);
+    stop(vec![node]);
+
+    // the node restarts without its entries, its next versions still come after the old ones
+    let (n, recovered): (Node<MemoryStorage>, bool) = recovering(&path);
+    assert!(recovered);
+    let node: Running = run(n);
+    put(&node.addr, 1, 1.0, 30);
+    assert!(get(&node.addr, 1).unwrap().1 > Version::new(19, 9));
+    stop(vec![node]);
+    let _ = remove_file(&path);
+}
@@ -0,0 +1,191 @@
+use copper::chord::entry::{Entry, Version};
+use copper::chord::storage::{LogStorage, MemoryStorage, Storage};
+use std::fs::{read_to_string, remove_file, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// A log file of its own for each test, removed when it is done.
+struct TempLog {
+    path: PathBuf,
+}
+
+impl TempLog {
+    fn new(name: &str) -> TempLog {
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("copper-{}-{}.log", name, std::process::id()));
+        let _ = remove_file(&path);
+        TempLog { path }
+    }
+
+    fn open(&self) -> LogStorage {
+        LogStorage::open(self.path.clone()).unwrap()
+    }
+
+    fn lines(&self) -> usize {
+        read_to_string(&self.path).unwrap().lines().count()
+    }
+}
+
+impl Drop for TempLog {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.path);
+        let _ = remove_file(self.path.with_extension("compact"));
+    }
+}
+
+fn entry(value: f64, counter: i64) -> Entry {
+    Entry::new(value, Version::new(counter, 1), None)
+}
+
+fn values(s: &LogStorage) -> Vec<(i64, f64)> {
+    let mut found: Vec<(i64, f64)> = s.iter().map(|(k, e)| (k, e.get_value())).collect();
+    found.sort_by_key(|(k, _)| *k);
+    found
+}
+
+#[test]
+fn the_log_is_replayed_when_reopened() {
+    let log: TempLog = TempLog::new("replay");
+    {
+        let mut s: LogStorage = log.open();
+        s.put(1, entry(1.0, 1));
+        s.put(2, entry(2.0, 2));
+        s.put(1, entry(3.0, 3));
+        s.put(4, Entry::new(4.0, Version::new(4, 1), Some(123)));
+        assert!(s.delete(2).is_some());
+        assert!(s.delete(9).is_none());
+    }
+    let s: LogStorage = log.open();
+    assert_eq!(values(&s), vec![(1, 3.0), (4, 4.0)]);
+    assert_eq!(s.get(1).unwrap().get_version(), Version::new(3, 1));
+    assert_eq!(s.get(4).unwrap().get_expires_at(), Some(123));
+}
+
+#[test]
+fn a_torn_record_is_dropped_and_later_writes_survive() {
+    let log: TempLog = TempLog::new("torn");
+    {
+        let mut s: LogStorage = log.open();
+        s.put(1, entry(1.0, 1));
+        s.put(2, entry(2.0, 2));
+    }
+    // a crash in the middle of a write
+    let mut f = OpenOptions::new().append(true).open(&log.path).unwrap();
+    write!(f, "{{\"op\":\"put\",\"key\":3,\"val").unwrap();
+    drop(f);
+    {
+        let mut s: LogStorage = log.open();
+        assert_eq!(values(&s), vec![(1, 1.0), (2, 2.0)]);
+        s.put(5, entry(5.0, 5));
+        s.delete(1);
+    }
+    let s: LogStorage = log.open();
+    assert_eq!(values(&s), vec![(2, 2.0), (5, 5.0)]);
+    assert_eq!(log.lines(), 4);
+}
+
+#[test]
+fn a_whole_record_without_its_end_of_line_is_dropped_too() {
+    let log: TempLog = TempLog::new("unterminated");
+    {
+        let mut s: LogStorage = log.open();
+        s.put(1, entry(1.0, 1));
+    }
+    let mut f = OpenOptions::new().append(true).open(&log.path).unwrap();
+    write!(
+        f,
+        "{{\"op\":\"put\",\"key\":3,\"value\":3.0,\"version\":{{\"counter\":3,\"writer\":1}}}}"
+    )
+    .unwrap();
+    drop(f);
+    {
+        let mut s: LogStorage = log.open();
+        s.put(6, entry(6.0, 6));
+    }
+    assert_eq!(values(&log.open()), vec![(1, 1.0), (6, 6.0)]);
+}
+
+#[test]
+fn compaction_keeps_only_the_live_entries() {
+    let log: TempLog = TempLog::new("compact");
+    {
+        let mut s: LogStorage = log.open();
+        for counter in 0..1100 {
+            s.put(1, entry(counter as f64, counter));
+        }
+        s.put(2, entry(2.0, 2000));
+        s.put(3, entry(3.0, 2001));
+        s.delete(3);
+        assert_eq!(log.lines(), 1103);
+        s.compact();
+        assert_eq!(log.lines(), 2);
+        // the log goes on after the compaction
+        s.put(7, entry(7.0, 2002));
+    }
+    let s: LogStorage = log.open();
+    assert_eq!(values(&s), vec![(1, 1099.0), (2, 2.0), (7, 7.0)]);
+    assert_eq!(log.lines(), 3);
+}
+
+fn sorted(mut keys: Vec<i64>) -> Vec<i64> {
+    keys.sort_unstable();
+    keys
+}
+
+/// Runs the `Storage` defaults that split the ring on `s`, which starts empty.
+fn check_ranges<S: Storage>(s: &mut S) {
+    // -5 and 40 sit at 27 and 8 on the ring
+    for (i, key) in [3, 10, 20, 31, -5, 40].iter().enumerate() {
+        s.put(*key, entry(*key as f64, i as i64));
+    }
+    let keys: Vec<i64> = s.range(-5, 20).into_iter().map(|(k, _)| k).collect();
+    assert_eq!(keys, vec![-5, 3, 10, 20]);
+    assert!(s.range(21, 30).is_empty());
+
+    assert_eq!(sorted(s.keys_in_range(3, 20)), vec![10, 20, 40]);
+    // the interval wraps around the end of the ring
+    assert_eq!(sorted(s.keys_in_range(20, 3)), vec![-5, 3, 31]);
+    // the same bounds are the whole ring
+    assert_eq!(sorted(s.keys_in_range(10, 10)).len(), 6);
+    assert!(s.keys_in_range(11, 19).is_empty());
+
+    let mut extracted: Vec<(i64, Entry)> = s.extract_range(20, 3);
+    extracted.sort_unstable_by_key(|(k, _)| *k);
+    let moved: Vec<(i64, f64)> = extracted.iter().map(|(k, e)| (*k, e.get_value())).collect();
+    assert_eq!(moved, vec![(-5, -5.0), (3, 3.0), (31, 31.0)]);
+    assert_eq!(sorted(s.keys()), vec![10, 20, 40]);
+    assert!(s.extract_range(20, 3).is_empty());
+}
+
+#[test]
+fn memory_storage_splits_the_ring() {
+    check_ranges(&mut MemoryStorage::new());
+}
+
+#[test]
+fn log_storage_splits_the_ring() {
+    let log: TempLog = TempLog::new("ranges");
+    {
+        let mut s: LogStorage = log.open();
+        check_ranges(&mut s);
+    }
+    // the extracted entries stay gone after a replay
+    assert_eq!(
+        values(&log.open()),
+        vec![(10, 10.0), (20, 20.0), (40, 40.0)]
+    );
+}
+
+#[cfg(feature = "sled")]
+#[test]
+fn sled_storage_splits_the_ring() {
+    use copper::chord::storage::SledStorage;
+
+    let path: PathBuf = std::env::temp_dir().join(format!("copper-sled-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    {
+        let mut s: SledStorage = SledStorage::open(path.clone()).unwrap();
+        check_ranges(&mut s);
+    }
+    let _ = std::fs::remove_dir_all(&path);
+}
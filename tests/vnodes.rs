@@ -0,0 +1,77 @@
+mod common;
+
+use common::{free_port, get, run_virtual, stop, wait_for_ring, Running};
+use copper::chord::address::Address;
+use copper::chord::entry::{Entry, Version};
+use copper::chord::message::Message::Move;
+use copper::chord::node::{Node, MAX_NODE};
+use copper::chord::storage::{MemoryStorage, SharedStorage, Storage};
+use std::net::Ipv4Addr;
+
+/// A storage holding every key of the ring, written before the server started.
+fn preloaded() -> SharedStorage {
+    let mut data: SharedStorage = SharedStorage::new(Box::new(MemoryStorage::new()));
+    for key in 0..MAX_NODE {
+        data.put(key, Entry::new(key as f64, Version::new(1, 0), None));
+    }
+    data
+}
+
+/// The virtual nodes `ids` of one server over `data`, each one joining through the first.
+fn server(ids: &[i64], data: &SharedStorage) -> Vec<Running> {
+    let port: i64 = free_port();
+    let nodes: Vec<Node<SharedStorage>> = ids
+        .iter()
+        .map(|&id| Node::new(Ipv4Addr::LOCALHOST, port, id, data.clone()))
+        .collect();
+    let first: Address = nodes[0].get_addr();
+    let nodes: Vec<Node<SharedStorage>> = nodes
+        .into_iter()
+        .map(|mut n| {
+            if n.get_addr() != first {
+                n.join(first.clone());
+            }
+            n
+        })
+        .collect();
+    run_virtual(nodes)
+}
+
+fn check_every_key(nodes: &[Running], data: &SharedStorage) {
+    assert_eq!(data.keys().len() as i64, MAX_NODE);
+    for key in 0..MAX_NODE {
+        for n in nodes {
+            assert_eq!(
+                get(&n.addr, key).map(|(v, _)| v),
+                Some(key as f64),
+                "{} lost from {:?}",
+                key,
+                n.addr
+            );
+        }
+    }
+}
+
+#[test]
+fn a_virtual_node_joining_through_a_sibling_keeps_the_shared_keys() {
+    let data: SharedStorage = preloaded();
+    let nodes: Vec<Running> = server(&[0, 16], &data);
+    wait_for_ring(&nodes);
+    check_every_key(&nodes, &data);
+    stop(nodes);
+}
+
+#[test]
+fn a_virtual_node_moving_next_to_a_sibling_keeps_the_shared_keys() {
+    let data: SharedStorage = preloaded();
+    let mut nodes: Vec<Running> = server(&[0, 16], &data);
+    wait_for_ring(&nodes);
+
+    // 0 hands its range to its successor 16, then joins again at 10 through it
+    nodes[0].addr.send_message(Move(nodes[1].addr.clone(), 10));
+    let moved: &mut Running = &mut nodes[0];
+    moved.addr = Address::new(moved.addr.get_ip(), moved.addr.get_port(), 10);
+    wait_for_ring(&nodes);
+    check_every_key(&nodes, &data);
+    stop(nodes);
+}